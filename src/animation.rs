@@ -0,0 +1,193 @@
+use serde_json::{Map, Value};
+
+//the scene as it is at a frame. any entry can have a keyframes list, each key giving a frame and values for some of the
+//entry's fields, and those fields are replaced by their value at the frame with the list dropped, so the rest of the
+//loader never sees animation. numbers and lists of numbers are interpolated, anything else holds until the next key
+pub fn resolve(data: &Value, frame: f32) -> Result<Value, String> {
+    match data {
+        Value::Array(items) => Ok(Value::Array(items.iter().map(|item| resolve(item, frame)).collect::<Result<_, _>>()?)),
+        Value::Object(entry) => {
+            let mut resolved: Map<String, Value> = entry.iter()
+                .filter(|(key, _)| key.as_str() != "keyframes")
+                .map(|(key, value)| Ok((key.clone(), resolve(value, frame)?)))
+                .collect::<Result<_, String>>()?;
+            if let Some(keys) = entry.get("keyframes") {
+                let keys = parse_keys(keys)?;
+                let mut fields: Vec<&String> = keys.iter()
+                    .flat_map(|key| key.values.keys())
+                    .filter(|field| field.as_str() != "frame" && field.as_str() != "interpolation")
+                    .collect();
+                fields.sort();
+                fields.dedup();
+                for field in fields {
+                    resolved.insert(field.clone(), evaluate(&keys, field, frame)?);
+                }
+            }
+            Ok(Value::Object(resolved))
+        }
+        other => Ok(other.clone()),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Interpolation {
+    Linear,
+    //smooth curve through the keys that eases in and out at the first and last ones
+    Bezier,
+}
+
+//interpolation is how the values get from this key to the next
+struct Key<'a> {
+    frame: f32,
+    interpolation: Interpolation,
+    values: &'a Map<String, Value>,
+}
+
+fn parse_keys(keys: &Value) -> Result<Vec<Key<'_>>, String> {
+    let mut keys: Vec<Key> = keys.as_array().ok_or("keyframes must be a list")?.iter().map(|key| {
+        let values = key.as_object().ok_or("keyframes must be objects")?;
+        let interpolation = match key["interpolation"].as_str() {
+            None | Some("linear") => Interpolation::Linear,
+            Some("bezier") => Interpolation::Bezier,
+            Some(other) => return Err(format!("unknown interpolation {}, expected linear or bezier", other)),
+        };
+        Ok(Key {
+            frame: key["frame"].as_f64().ok_or("keyframes need a frame")? as f32,
+            interpolation,
+            values,
+        })
+    }).collect::<Result<_, String>>()?;
+    keys.sort_by(|a, b| a.frame.total_cmp(&b.frame));
+    Ok(keys)
+}
+
+//a number or a list of numbers as a list, None for anything that can't be interpolated
+fn numbers(value: &Value) -> Option<Vec<f64>> {
+    match value {
+        Value::Number(number) => number.as_f64().map(|number| vec![number]),
+        Value::Array(items) => items.iter().map(|item| item.as_f64()).collect(),
+        _ => None,
+    }
+}
+
+fn evaluate(keys: &[Key], field: &str, frame: f32) -> Result<Value, String> {
+    //only the keys that set this field count, others are for the entry's other fields
+    let keys: Vec<(&Key, &Value)> = keys.iter()
+        .filter_map(|key| key.values.get(field).map(|value| (key, value)))
+        .collect();
+    let next = keys.iter().position(|(key, _)| key.frame > frame).unwrap_or(keys.len());
+    if next == 0 {
+        return Ok(keys[0].1.clone());
+    }
+    if next == keys.len() {
+        return Ok(keys[next - 1].1.clone());
+    }
+    let (start, start_value) = keys[next - 1];
+    let (end, end_value) = keys[next];
+    let (Some(from), Some(to)) = (numbers(start_value), numbers(end_value)) else {
+        return Ok(start_value.clone());
+    };
+    if from.len() != to.len() {
+        return Err(format!("keyframes for {} must all have the same number of values", field));
+    }
+    let length = (end.frame - start.frame) as f64;
+    let t = (frame - start.frame) as f64 / length;
+    let values: Vec<f64> = match start.interpolation {
+        Interpolation::Linear => (0..from.len()).map(|i| from[i] + (to[i] - from[i]) * t).collect(),
+        Interpolation::Bezier => {
+            //handles a third of the way along, pointing the way the neighbouring keys go, flat at the ends
+            let slope = |index: usize| -> Vec<f64> {
+                if index == 0 || index + 1 == keys.len() {
+                    return vec![0.0; from.len()];
+                }
+                let (before, before_value) = keys[index - 1];
+                let (after, after_value) = keys[index + 1];
+                match (numbers(before_value), numbers(after_value)) {
+                    (Some(before_value), Some(after_value)) if before_value.len() == from.len() && after_value.len() == from.len() => {
+                        (0..from.len()).map(|i| (after_value[i] - before_value[i]) / (after.frame - before.frame) as f64).collect()
+                    }
+                    _ => vec![0.0; from.len()],
+                }
+            };
+            let (start_slope, end_slope) = (slope(next - 1), slope(next));
+            (0..from.len()).map(|i| {
+                let control = [from[i], from[i] + start_slope[i] * length / 3.0, to[i] - end_slope[i] * length / 3.0, to[i]];
+                let s = 1.0 - t;
+                control[0] * s * s * s + 3.0 * control[1] * s * s * t + 3.0 * control[2] * s * t * t + control[3] * t * t * t
+            }).collect()
+        }
+    };
+    if let Value::Number(_) = start_value {
+        return Ok(Value::from(values[0]));
+    }
+    Ok(Value::Array(values.into_iter().map(Value::from).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn at(entry: &Value, frame: f32, field: &str) -> Value {
+        resolve(entry, frame).unwrap()[field].clone()
+    }
+
+    fn close(value: Value, expected: f64) {
+        assert!((value.as_f64().unwrap() - expected).abs() < 1e-4, "{} instead of {}", value, expected);
+    }
+
+    #[test]
+    fn linear_keys_are_interpolated() {
+        let entry = json!({"radius": 1, "keyframes": [
+            {"frame": 0, "position": [0, 0, 0], "radius": 1},
+            {"frame": 10, "position": [10, 20, -10], "radius": 3},
+        ]});
+        assert_eq!(at(&entry, 5.0, "position"), json!([5.0, 10.0, -5.0]));
+        close(at(&entry, 2.5, "radius"), 1.5);
+        assert!(resolve(&entry, 5.0).unwrap().get("keyframes").is_none());
+    }
+
+    #[test]
+    fn bezier_keys_ease_in_and_out() {
+        let entry = json!({"keyframes": [
+            {"frame": 0, "x": 0, "interpolation": "bezier"},
+            {"frame": 10, "x": 10},
+        ]});
+        //flat at both ends, so 10 t^2 (3 - 2t)
+        close(at(&entry, 2.0, "x"), 1.04);
+        close(at(&entry, 5.0, "x"), 5.0);
+        close(at(&entry, 8.0, "x"), 8.96);
+    }
+
+    #[test]
+    fn values_hold_outside_the_keys() {
+        let entry = json!({"keyframes": [
+            {"frame": 10, "x": 1},
+            {"frame": 20, "x": 2},
+        ]});
+        close(at(&entry, 0.0, "x"), 1.0);
+        close(at(&entry, 30.0, "x"), 2.0);
+    }
+
+    #[test]
+    fn other_values_hold_until_the_next_key() {
+        let entry = json!({"keyframes": [
+            {"frame": 0, "filename": "a.obj", "x": 0},
+            {"frame": 10, "filename": "b.obj"},
+            {"frame": 20, "x": 2},
+        ]});
+        assert_eq!(at(&entry, 9.9, "filename"), json!("a.obj"));
+        assert_eq!(at(&entry, 10.0, "filename"), json!("b.obj"));
+        //keys without the field are skipped, so x goes straight from 0 to 20
+        close(at(&entry, 10.0, "x"), 1.0);
+    }
+
+    #[test]
+    fn bad_keyframes_are_errors() {
+        assert!(resolve(&json!({"keyframes": {"frame": 0}}), 0.0).is_err());
+        assert!(resolve(&json!({"keyframes": [{"x": 1}]}), 0.0).is_err());
+        assert!(resolve(&json!([{"keyframes": [{"frame": 0, "x": 0, "interpolation": "cubic"}]}]), 0.0).is_err());
+        let mismatched = json!({"keyframes": [{"frame": 0, "x": [0, 0]}, {"frame": 1, "x": [1, 1, 1]}]});
+        assert_eq!(resolve(&mismatched, 0.5).err().unwrap(), "keyframes for x must all have the same number of values");
+    }
+}
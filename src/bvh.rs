@@ -0,0 +1,323 @@
+use std::sync::Arc;
+use rand::prelude::*;
+use super::objects::{Hit, Hittable, Ray, SurfaceSample};
+
+//most nodes a leaf may hold before splitting stops being worth it
+const MAX_LEAF_SIZE: usize = 4;
+//buckets along the split axis when estimating the surface area heuristic
+const BIN_COUNT: usize = 12;
+//deepest a node can be, traversal visits one side and keeps the other so its stack never holds more than this plus one
+const MAX_DEPTH: usize = 62;
+
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    pub fn new(min: [f32; 3], max: [f32; 3]) -> Aabb {
+        Aabb {
+            min,
+            max,
+        }
+    }
+    //contains nothing, growing it by anything gives that thing's box
+    pub fn empty() -> Aabb {
+        Aabb::new([f32::INFINITY; 3], [f32::NEG_INFINITY; 3])
+    }
+    pub fn from_points(points: &[[f32; 3]]) -> Aabb {
+        points.iter().fold(Aabb::empty(), |bounds, &point| bounds.grow(point))
+    }
+    pub fn grow(&self, point: [f32; 3]) -> Aabb {
+        Aabb::new(
+            [self.min[0].min(point[0]), self.min[1].min(point[1]), self.min[2].min(point[2])],
+            [self.max[0].max(point[0]), self.max[1].max(point[1]), self.max[2].max(point[2])],
+        )
+    }
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(
+            [self.min[0].min(other.min[0]), self.min[1].min(other.min[1]), self.min[2].min(other.min[2])],
+            [self.max[0].max(other.max[0]), self.max[1].max(other.max[1]), self.max[2].max(other.max[2])],
+        )
+    }
+    pub fn centroid(&self) -> [f32; 3] {
+        [(self.min[0] + self.max[0]) / 2.0, (self.min[1] + self.max[1]) / 2.0, (self.min[2] + self.max[2]) / 2.0]
+    }
+    pub fn surface_area(&self) -> f32 {
+        let size = [self.max[0] - self.min[0], self.max[1] - self.min[1], self.max[2] - self.min[2]];
+        if size.iter().any(|&length| length < 0.0) {
+            return 0.0;
+        }
+        2.0 * (size[0] * size[1] + size[1] * size[2] + size[2] * size[0])
+    }
+    //distances where the ray enters and leaves the box, clipped to t_min and t_max
+    pub fn hit(&self, origin: [f32; 3], inverse_direction: [f32; 3], t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        let mut near = t_min;
+        let mut far = t_max;
+        for i in 0..3 {
+            let t1 = (self.min[i] - origin[i]) * inverse_direction[i];
+            let t2 = (self.max[i] - origin[i]) * inverse_direction[i];
+            //written so a nan from 0 * infinity leaves the range alone
+            near = if t1.min(t2) > near { t1.min(t2) } else { near };
+            far = if t1.max(t2) < far { t1.max(t2) } else { far };
+        }
+        if near <= far { Some((near, far)) } else { None }
+    }
+}
+
+enum Node {
+    Leaf { bounds: Aabb, first: usize, count: usize },
+    //the left child always directly follows its parent
+    Branch { bounds: Aabb, right: usize, axis: usize },
+}
+
+impl Node {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Branch { bounds, .. } => bounds,
+        }
+    }
+}
+
+struct Item {
+    object: Arc<dyn Hittable>,
+    index: usize,
+    bounds: Aabb,
+    centroid: [f32; 3],
+}
+
+//bounding volume hierarchy over everything in the scene, shapes without bounds are tested on every ray,
+//hits are tagged with where the object they came from was in the list it was built from
+pub struct Bvh {
+    nodes: Vec<Node>,
+    objects: Vec<Arc<dyn Hittable>>,
+    //the tag for each of objects
+    ids: Vec<u32>,
+    unbounded: Vec<(u32, Arc<dyn Hittable>)>,
+}
+
+impl Bvh {
+    pub fn new(objects: Vec<Arc<dyn Hittable>>) -> Bvh {
+        let mut items = vec![];
+        let mut unbounded = vec![];
+        for (index, object) in objects.into_iter().enumerate() {
+            match object.bounds() {
+                Some(bounds) => items.push(Item {
+                    object,
+                    index,
+                    bounds,
+                    centroid: bounds.centroid(),
+                }),
+                None => unbounded.push((index as u32 + 1, object)),
+            }
+        }
+        let mut nodes = Vec::with_capacity(items.len() * 2);
+        if !items.is_empty() {
+            build(&mut items, 0, 0, &mut nodes);
+        }
+        Bvh {
+            nodes,
+            ids: items.iter().map(|item| item.index as u32 + 1).collect(),
+            objects: items.into_iter().map(|item| item.object).collect(),
+            unbounded,
+        }
+    }
+}
+
+//a bvh can itself be placed in a scene, which is how meshes become a single closed shape for csg
+impl Hittable for Bvh {
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+        let mut closest: Option<Hit> = None;
+        let mut t_max = t_max;
+        for (id, object) in &self.unbounded {
+            if let Some(mut hit) = object.intersect(ray, t_min, t_max) {
+                t_max = hit.t;
+                hit.object = *id;
+                closest = Some(hit);
+            }
+        }
+        if self.nodes.is_empty() {
+            return closest;
+        }
+
+        let inverse_direction = ray.direction.map(|value| 1.0 / value);
+        let mut stack = [0; MAX_DEPTH + 2];
+        let mut stack_size = 1;
+        while stack_size > 0 {
+            stack_size -= 1;
+            let index = stack[stack_size];
+            let node = &self.nodes[index];
+            if node.bounds().hit(ray.origin, inverse_direction, t_min, t_max).is_none() {
+                continue;
+            }
+            match *node {
+                Node::Leaf { first, count, .. } => {
+                    for (object, id) in self.objects[first..first + count].iter().zip(&self.ids[first..first + count]) {
+                        if let Some(mut hit) = object.intersect(ray, t_min, t_max) {
+                            t_max = hit.t;
+                            hit.object = *id;
+                            closest = Some(hit);
+                        }
+                    }
+                }
+                Node::Branch { right, axis, .. } => {
+                    //visit the child nearer the ray first so the far one is more often culled
+                    let (near, far) = if ray.direction[axis] < 0.0 { (right, index + 1) } else { (index + 1, right) };
+                    stack[stack_size] = far;
+                    stack[stack_size + 1] = near;
+                    stack_size += 2;
+                }
+            }
+        }
+        closest
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.nodes.first().map(|node| *node.bounds())
+    }
+    fn sample_surface(&self, _rng: &mut ThreadRng) -> Option<SurfaceSample> {
+        None
+    }
+}
+
+//builds the subtree for items, whose first element sits at offset in the final object list,
+//past the depth limit whatever is left stays in one leaf
+fn build(items: &mut [Item], offset: usize, depth: usize, nodes: &mut Vec<Node>) {
+    let bounds = items.iter().fold(Aabb::empty(), |bounds, item| bounds.union(&item.bounds));
+    let index = nodes.len();
+    nodes.push(Node::Leaf {
+        bounds,
+        first: offset,
+        count: items.len(),
+    });
+    if items.len() <= MAX_LEAF_SIZE || depth == MAX_DEPTH {
+        return;
+    }
+
+    let centroids = items.iter().fold(Aabb::empty(), |bounds, item| bounds.grow(item.centroid));
+    let extent = [centroids.max[0] - centroids.min[0], centroids.max[1] - centroids.min[1], centroids.max[2] - centroids.min[2]];
+    let axis = if extent[0] > extent[1] && extent[0] > extent[2] { 0 } else if extent[1] > extent[2] { 1 } else { 2 };
+    if extent[axis] <= 0.0 {
+        return;
+    }
+
+    //sort the items into buckets and find the cheapest place to cut between them
+    let bin_of = |item: &Item| (((item.centroid[axis] - centroids.min[axis]) / extent[axis] * BIN_COUNT as f32) as usize).min(BIN_COUNT - 1);
+    let mut bins = [(Aabb::empty(), 0usize); BIN_COUNT];
+    for item in items.iter() {
+        let bin = &mut bins[bin_of(item)];
+        bin.0 = bin.0.union(&item.bounds);
+        bin.1 += 1;
+    }
+    let mut best_cost = f32::INFINITY;
+    let mut best_split = 0;
+    for split in 1..BIN_COUNT {
+        let (left, right) = bins.split_at(split);
+        let side = |bins: &[(Aabb, usize)]| bins.iter().fold((Aabb::empty(), 0), |(bounds, count), bin| (bounds.union(&bin.0), count + bin.1));
+        let (left_bounds, left_count) = side(left);
+        let (right_bounds, right_count) = side(right);
+        if left_count == 0 || right_count == 0 {
+            continue;
+        }
+        let cost = left_bounds.surface_area() * left_count as f32 + right_bounds.surface_area() * right_count as f32;
+        if cost < best_cost {
+            best_cost = cost;
+            best_split = split;
+        }
+    }
+    //a leaf costs one test per item over the whole box
+    if best_split == 0 || best_cost >= bounds.surface_area() * items.len() as f32 {
+        return;
+    }
+
+    let mut middle = 0;
+    for i in 0..items.len() {
+        if bin_of(&items[i]) < best_split {
+            items.swap(i, middle);
+            middle += 1;
+        }
+    }
+    let (left, right) = items.split_at_mut(middle);
+    build(left, offset, depth + 1, nodes);
+    let right_index = nodes.len();
+    build(right, offset + middle, depth + 1, nodes);
+    nodes[index] = Node::Branch {
+        bounds,
+        right: right_index,
+        axis,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use super::super::objects::{normalize, Material, Pattern, Plane, Sphere};
+
+    //closest hit by testing every object, tagged the way the bvh tags them
+    fn brute_force(objects: &[Arc<dyn Hittable>], ray: &Ray) -> Option<(f32, u32)> {
+        objects.iter().enumerate()
+            .filter_map(|(index, object)| object.intersect(ray, 0.001, f32::INFINITY).map(|hit| (hit.t, index as u32 + 1)))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+
+    //rays from anywhere in the cube of size around the origin towards a random one of the targets, a little off
+    fn assert_matches(objects: Vec<Arc<dyn Hittable>>, targets: &[[f32; 3]], size: f32, rng: &mut StdRng) {
+        let bvh = Bvh::new(objects.clone());
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = [0; 3].map(|_| rng.gen_range(-size..size));
+            let target = targets[rng.gen_range(0..targets.len())];
+            let direction = normalize([0, 1, 2].map(|i| target[i] + rng.gen_range(-1.0..1.0) - origin[i]));
+            let ray = Ray::new(origin, direction);
+            let expected = brute_force(&objects, &ray);
+            let found = bvh.intersect(&ray, 0.001, f32::INFINITY).map(|hit| (hit.t, hit.object));
+            assert_eq!(found, expected, "ray from {:?} along {:?}", ray.origin, ray.direction);
+            hits += expected.is_some() as usize;
+        }
+        assert!(hits > 500, "only {} rays hit anything", hits);
+    }
+
+    fn depth(nodes: &[Node], index: usize) -> usize {
+        match nodes[index] {
+            Node::Leaf { .. } => 0,
+            Node::Branch { right, .. } => 1 + depth(nodes, index + 1).max(depth(nodes, right)),
+        }
+    }
+
+    #[test]
+    fn traversal_finds_the_closest_hit() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let material = Material::new([1.0; 3], 0.0, 0.0);
+        let centers: Vec<[f32; 3]> = (0..300).map(|_| [0; 3].map(|_| rng.gen_range(-10.0..10.0))).collect();
+        let mut objects: Vec<Arc<dyn Hittable>> = centers.iter()
+            .map(|&center| Arc::new(Sphere::new(center, rng.gen_range(0.1..1.5), material.clone())) as Arc<dyn Hittable>)
+            .collect();
+        //planes have no bounds and are tested on every ray next to the tree
+        objects.insert(40, Arc::new(Plane::new([0.0, -8.0, 0.0], [0.0, 1.0, 0.0], material.clone(), Pattern::None)));
+        objects.push(Arc::new(Plane::new([12.0, 0.0, 0.0], [-1.0, 0.2, 0.0], material, Pattern::None)));
+        assert_matches(objects, &centers, 15.0, &mut rng);
+    }
+
+    #[test]
+    fn traversal_works_past_the_depth_limit() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let material = Material::new([1.0; 3], 0.0, 0.0);
+        //each sphere thirteen times as far along its axis as the last one on that axis, so every split only peels off the farthest
+        let centers: Vec<[f32; 3]> = (0..75).map(|i| {
+            let mut center = [0.0; 3];
+            center[i % 3] = 1e-10 * 13f32.powi(i as i32 / 3);
+            center
+        }).collect();
+        let objects: Vec<Arc<dyn Hittable>> = centers.iter()
+            .map(|&center| Arc::new(Sphere::new(center, 0.01 * (center[0] + center[1] + center[2]), material.clone())) as Arc<dyn Hittable>)
+            .collect();
+        let bvh = Bvh::new(objects.clone());
+        assert_eq!(depth(&bvh.nodes, 0), MAX_DEPTH);
+        assert!(bvh.nodes.iter().any(|node| matches!(node, Node::Leaf { count, .. } if *count > MAX_LEAF_SIZE)));
+        assert_matches(objects, &centers[30..50], 5.0, &mut rng);
+    }
+}
@@ -0,0 +1,176 @@
+use std::sync::Arc;
+use rand::prelude::*;
+use super::bvh::Aabb;
+use super::objects::{Hit, Hittable, Ray, SurfaceSample};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Operation {
+    Union,
+    Intersection,
+    //left with right cut out of it
+    Difference,
+}
+
+impl Operation {
+    pub fn from_name(name: &str) -> Option<Operation> {
+        match name {
+            "union" => Some(Operation::Union),
+            "intersection" => Some(Operation::Intersection),
+            "difference" => Some(Operation::Difference),
+            _ => None,
+        }
+    }
+    fn inside(&self, left: bool, right: bool) -> bool {
+        match self {
+            Operation::Union => left || right,
+            Operation::Intersection => left && right,
+            Operation::Difference => left && !right,
+        }
+    }
+}
+
+//combines two closed shapes by walking the ray through the intervals where it is inside each of them,
+//the surfaces of either child only count where being inside the result changes
+#[derive(Clone)]
+pub struct Csg {
+    pub operation: Operation,
+    pub left: Arc<dyn Hittable>,
+    pub right: Arc<dyn Hittable>,
+}
+
+impl Csg {
+    pub fn new(operation: Operation, left: Arc<dyn Hittable>, right: Arc<dyn Hittable>) -> Csg {
+        Csg {
+            operation,
+            left,
+            right,
+        }
+    }
+}
+
+impl Hittable for Csg {
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+        let mut left_hit = self.left.intersect(ray, t_min, f32::INFINITY);
+        let mut right_hit = self.right.intersect(ray, t_min, f32::INFINITY);
+        //leaving a closed shape first means the ray started inside it
+        let mut inside_left = left_hit.as_ref().is_some_and(|hit| !hit.front_face);
+        let mut inside_right = right_hit.as_ref().is_some_and(|hit| !hit.front_face);
+
+        loop {
+            let left_t = left_hit.as_ref().map_or(f32::INFINITY, |hit| hit.t);
+            let right_t = right_hit.as_ref().map_or(f32::INFINITY, |hit| hit.t);
+            if left_t.min(right_t) >= t_max {
+                return None;
+            }
+            let was_inside = self.operation.inside(inside_left, inside_right);
+            let hit = if left_t <= right_t {
+                let hit = left_hit.take().unwrap();
+                inside_left = hit.front_face;
+                left_hit = self.left.intersect(ray, hit.t, f32::INFINITY);
+                hit
+            } else {
+                let hit = right_hit.take().unwrap();
+                inside_right = hit.front_face;
+                right_hit = self.right.intersect(ray, hit.t, f32::INFINITY);
+                hit
+            };
+            let is_inside = self.operation.inside(inside_left, inside_right);
+            if was_inside != is_inside {
+                //the normal already faces the ray, only which side counts as outside changes,
+                //so a surface cut out by a difference is seen from its inside
+                let mut hit = hit;
+                hit.front_face = is_inside;
+                return Some(hit);
+            }
+        }
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        match (self.operation, self.left.bounds(), self.right.bounds()) {
+            (Operation::Union, Some(left), Some(right)) => Some(left.union(&right)),
+            (Operation::Union, _, _) => None,
+            (Operation::Intersection, Some(left), Some(right)) => Some(Aabb::new(
+                [left.min[0].max(right.min[0]), left.min[1].max(right.min[1]), left.min[2].max(right.min[2])],
+                [left.max[0].min(right.max[0]), left.max[1].min(right.max[1]), left.max[2].min(right.max[2])],
+            )),
+            (Operation::Intersection, left, right) => left.or(right),
+            (Operation::Difference, left, _) => left,
+        }
+    }
+    //which parts of the children's surfaces survive isn't known without tracing, so csg shapes can't be sampled
+    fn sample_surface(&self, _rng: &mut ThreadRng) -> Option<SurfaceSample> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::objects::{Material, Sphere};
+    use super::super::primitives::{Cuboid, Transform};
+
+    //a unit sphere and a square bar through it along z, 0.6 wide and sticking out 2 each way
+    fn shape(operation: Operation) -> Csg {
+        let material = Material::new([1.0; 3], 0.0, 0.0);
+        Csg::new(
+            operation,
+            Arc::new(Sphere::new([0.0; 3], 1.0, material.clone())),
+            Arc::new(Cuboid::new(Transform::new([0.0; 3], [0.0; 3]), [0.6, 0.6, 4.0], material)),
+        )
+    }
+
+    fn trace(shape: &Csg, origin: [f32; 3], direction: [f32; 3]) -> Option<Hit> {
+        shape.intersect(&Ray::new(origin, direction), 0.0, f32::INFINITY)
+    }
+
+    fn assert_hit(hit: Option<Hit>, t: f32, normal: [f32; 3], front_face: bool) {
+        let hit = hit.expect("the ray should hit");
+        assert!((hit.t - t).abs() < 1e-4, "hit at {} instead of {}", hit.t, t);
+        for i in 0..3 {
+            assert!((hit.normal[i] - normal[i]).abs() < 1e-4, "normal {:?} instead of {:?}", hit.normal, normal);
+        }
+        assert_eq!(hit.front_face, front_face);
+    }
+
+    #[test]
+    fn rays_through_the_hole_miss() {
+        let shape = shape(Operation::Difference);
+        assert!(trace(&shape, [0.0, 0.0, -5.0], [0.0, 0.0, 1.0]).is_none());
+        assert!(trace(&shape, [0.1, -0.2, 5.0], [0.0, 0.0, -1.0]).is_none());
+    }
+
+    #[test]
+    fn cut_faces_point_into_the_hole() {
+        let shape = shape(Operation::Difference);
+        //from the middle of the hole the wall of the bar is the outside of the shape
+        assert_hit(trace(&shape, [0.0; 3], [-1.0, 0.0, 0.0]), 0.3, [1.0, 0.0, 0.0], true);
+        //coming the other way the ray enters through the sphere and leaves through the same wall
+        assert_hit(trace(&shape, [-5.0, 0.0, 0.0], [1.0, 0.0, 0.0]), 4.0, [-1.0, 0.0, 0.0], true);
+        assert_hit(trace(&shape, [-0.9, 0.0, 0.0], [1.0, 0.0, 0.0]), 0.6, [-1.0, 0.0, 0.0], false);
+    }
+
+    #[test]
+    fn rays_starting_inside_find_their_exit() {
+        let shape = shape(Operation::Difference);
+        assert_hit(trace(&shape, [-0.6, 0.0, 0.0], [-1.0, 0.0, 0.0]), 0.4, [1.0, 0.0, 0.0], false);
+        assert_hit(trace(&shape, [-0.6, 0.0, 0.0], [1.0, 0.0, 0.0]), 0.3, [-1.0, 0.0, 0.0], false);
+    }
+
+    #[test]
+    fn unions_skip_the_surfaces_inside_each_other() {
+        let shape = shape(Operation::Union);
+        assert_hit(trace(&shape, [0.0, 0.0, -5.0], [0.0, 0.0, 1.0]), 3.0, [0.0, 0.0, -1.0], true);
+        //the sphere's surface at z = 1 is inside the bar
+        assert_hit(trace(&shape, [0.0; 3], [0.0, 0.0, 1.0]), 2.0, [0.0, 0.0, -1.0], false);
+        assert_hit(trace(&shape, [0.0; 3], [1.0, 0.0, 0.0]), 1.0, [-1.0, 0.0, 0.0], false);
+    }
+
+    #[test]
+    fn intersections_keep_only_the_shared_part() {
+        let shape = shape(Operation::Intersection);
+        assert_hit(trace(&shape, [0.0, 0.0, -5.0], [0.0, 0.0, 1.0]), 4.0, [0.0, 0.0, -1.0], true);
+        //the sphere is entered first but the bar's side is where both are
+        assert_hit(trace(&shape, [-5.0, 0.0, 0.0], [1.0, 0.0, 0.0]), 4.7, [-1.0, 0.0, 0.0], true);
+        assert_hit(trace(&shape, [0.0; 3], [1.0, 0.0, 0.0]), 0.3, [-1.0, 0.0, 0.0], false);
+        assert!(trace(&shape, [-5.0, 0.5, 0.0], [1.0, 0.0, 0.0]).is_none());
+    }
+}
@@ -0,0 +1,149 @@
+use std::thread;
+use super::framebuffer::{Framebuffer, luminance};
+
+//each pass of the filter reaches twice as far as the last, 5 passes cover 2 * 31 pixels across
+const ITERATIONS: usize = 5;
+//b3 spline weights for the centre tap and the ones 1 and 2 steps out
+const KERNEL: [f32; 3] = [3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+//how many standard deviations of noise two pixels can differ by and still blur together
+const COLOR_SIGMA: f32 = 4.0;
+//how sharply differently facing surfaces stop blurring together
+const NORMAL_POWER: i32 = 64;
+//depth difference allowed per pixel of distance, as a fraction of the depth
+const DEPTH_SIGMA: f32 = 0.02;
+//albedo below this isn't divided out, it would blow up the noise
+const MIN_ALBEDO: f32 = 0.01;
+
+//edge avoiding a-trous wavelet filter, noisy lighting is blurred across surfaces that face the same way at the same depth
+//while the variance of each pixel decides how far apart in brightness neighbours can be. lighting is filtered with the
+//albedo divided out so textures stay sharp, and emission, which includes sky seen directly, is left alone
+pub fn denoise(framebuffer: &Framebuffer) -> Framebuffer {
+    let albedo: Vec<[f32; 3]> = framebuffer.pixels.iter().map(|pixel| pixel.albedo.map(|value| if value > MIN_ALBEDO { value } else { 1.0 })).collect();
+    let mut lighting: Vec<[f32; 3]> = framebuffer.pixels.iter().zip(&albedo).map(|(pixel, albedo)| {
+        [0, 1, 2].map(|i| (pixel.direct[i] + pixel.indirect[i]) / albedo[i])
+    }).collect();
+    let mut variance: Vec<f32> = framebuffer.pixels.iter().zip(&albedo).map(|(pixel, albedo)| {
+        pixel.variance / luminance(*albedo).max(MIN_ALBEDO).powi(2)
+    }).collect();
+    if framebuffer.pixels.iter().any(|pixel| pixel.samples < 2) {
+        //one sample says nothing about its own spread so go by how much the neighbours disagree
+        variance = spatial_variance(framebuffer.width, framebuffer.height, &lighting);
+    }
+
+    for iteration in 0..ITERATIONS {
+        (lighting, variance) = filter_pass(framebuffer, &lighting, &variance, 1 << iteration);
+    }
+
+    let mut denoised = framebuffer.clone();
+    for ((pixel, lighting), albedo) in denoised.pixels.iter_mut().zip(lighting).zip(albedo) {
+        pixel.color = [0, 1, 2].map(|i| pixel.emission[i] + lighting[i] * albedo[i]);
+    }
+    denoised
+}
+
+fn filter_pass(framebuffer: &Framebuffer, lighting: &[[f32; 3]], variance: &[f32], step: usize) -> (Vec<[f32; 3]>, Vec<f32>) {
+    let (width, height) = (framebuffer.width, framebuffer.height);
+    let blurred_variance = blur_variance(width, height, variance);
+    let mut next_lighting = vec![[0.0; 3]; width * height];
+    let mut next_variance = vec![0.0; width * height];
+
+    let threads = thread::available_parallelism().map_or(1, |count| count.get());
+    let rows_per_thread = height.div_ceil(threads);
+    thread::scope(|scope| {
+        let lighting_chunks = next_lighting.chunks_mut(rows_per_thread * width);
+        let variance_chunks = next_variance.chunks_mut(rows_per_thread * width);
+        for (chunk, (lighting_out, variance_out)) in lighting_chunks.zip(variance_chunks).enumerate() {
+            let blurred_variance = &blurred_variance;
+            scope.spawn(move || {
+                for (offset, (lighting_out, variance_out)) in lighting_out.iter_mut().zip(variance_out.iter_mut()).enumerate() {
+                    let index = chunk * rows_per_thread * width + offset;
+                    let (x, y) = (index % width, index / width);
+                    let centre = &framebuffer.pixels[index];
+                    let centre_luminance = luminance(lighting[index]);
+                    let sigma = COLOR_SIGMA * blurred_variance[index].sqrt() + 1e-4;
+
+                    let mut weight_sum = 0.0;
+                    let mut lighting_sum = [0.0; 3];
+                    let mut variance_sum = 0.0;
+                    for dy in -2_i64..=2 {
+                        for dx in -2_i64..=2 {
+                            let other_x = x as i64 + dx * step as i64;
+                            let other_y = y as i64 + dy * step as i64;
+                            if other_x < 0 || other_y < 0 || other_x >= width as i64 || other_y >= height as i64 {
+                                continue;
+                            }
+                            let other_index = other_y as usize * width + other_x as usize;
+                            let other = &framebuffer.pixels[other_index];
+
+                            let mut weight = KERNEL[dx.unsigned_abs() as usize] * KERNEL[dy.unsigned_abs() as usize];
+                            if other_index != index {
+                                let normal = [0, 1, 2].map(|i| centre.normal[i] * other.normal[i]).iter().sum::<f32>().max(0.0);
+                                let distance = ((dx * dx + dy * dy) as f32).sqrt() * step as f32;
+                                let depth = (centre.depth - other.depth).abs() / (DEPTH_SIGMA * centre.depth.max(other.depth) * distance + 1e-4);
+                                let brightness = (centre_luminance - luminance(lighting[other_index])).abs() / sigma;
+                                weight *= normal.powi(NORMAL_POWER) * (-depth - brightness).exp();
+                            }
+                            weight_sum += weight;
+                            lighting_sum = [0, 1, 2].map(|i| lighting_sum[i] + lighting[other_index][i] * weight);
+                            variance_sum += variance[other_index] * weight * weight;
+                        }
+                    }
+                    //the centre always counts so the sum is never 0
+                    *lighting_out = lighting_sum.map(|value| value / weight_sum);
+                    *variance_out = variance_sum / (weight_sum * weight_sum);
+                }
+            });
+        }
+    });
+    (next_lighting, next_variance)
+}
+
+//3x3 gaussian, a single pixel's variance is too noisy to steer the filter by itself
+fn blur_variance(width: usize, height: usize, variance: &[f32]) -> Vec<f32> {
+    let weights = [0.25, 0.5, 0.25];
+    let mut blurred = vec![0.0; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = 0.0;
+            let mut weight_sum = 0.0;
+            for dy in 0..3 {
+                for dx in 0..3 {
+                    let (Some(other_x), Some(other_y)) = ((x + dx).checked_sub(1), (y + dy).checked_sub(1)) else {
+                        continue;
+                    };
+                    if other_x >= width || other_y >= height {
+                        continue;
+                    }
+                    let weight = weights[dx] * weights[dy];
+                    sum += variance[other_y * width + other_x] * weight;
+                    weight_sum += weight;
+                }
+            }
+            blurred[y * width + x] = sum / weight_sum;
+        }
+    }
+    blurred
+}
+
+//variance of the luminance over the 3x3 block around each pixel
+fn spatial_variance(width: usize, height: usize, lighting: &[[f32; 3]]) -> Vec<f32> {
+    let mut variance = vec![0.0; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = 0.0;
+            let mut squares = 0.0;
+            let mut count = 0.0;
+            for other_y in y.saturating_sub(1)..(y + 2).min(height) {
+                for other_x in x.saturating_sub(1)..(x + 2).min(width) {
+                    let value = luminance(lighting[other_y * width + other_x]);
+                    sum += value;
+                    squares += value * value;
+                    count += 1.0;
+                }
+            }
+            let mean = sum / count;
+            variance[y * width + x] = (squares / count - mean * mean).max(0.0);
+        }
+    }
+    variance
+}
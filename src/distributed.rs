@@ -0,0 +1,384 @@
+use std::collections::{HashSet, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json::{json, Value};
+use super::filters::Filter;
+use super::framebuffer::{Framebuffer, Tile};
+use super::png_manager::Image;
+use super::preview::Preview;
+use super::scene_manager::{self, PathSettings, Scene, SceneCache};
+use super::{status_print, store_chunk};
+
+//a worker that hasn't sent a tile back in this long is taken to be lost and its tile goes to someone else
+const TILE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+//how often a connection with nothing to hand out checks whether work has come back or the render is over
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+//everything a worker needs to render tiles that match the coordinator's, sent as one line of json when it connects.
+//the scene is a path, so every worker needs the scene file and the meshes and textures it uses in the same place
+pub struct Job {
+    pub scene: String,
+    pub width: usize,
+    pub height: usize,
+    pub chunk: usize,
+    pub samples: usize,
+    pub antialiasing: bool,
+    pub fov: f32,
+    pub settings: PathSettings,
+    pub filter: Filter,
+    pub shutter: f32,
+}
+
+impl Job {
+    fn to_json(&self) -> String {
+        json!({
+            "scene": self.scene,
+            "width": self.width,
+            "height": self.height,
+            "chunk": self.chunk,
+            "samples": self.samples,
+            "antialiasing": self.antialiasing,
+            "fov": self.fov,
+            "min_bounces": self.settings.min_bounces,
+            "max_diffuse": self.settings.max_diffuse,
+            "max_specular": self.settings.max_specular,
+            "max_transmission": self.settings.max_transmission,
+            "filter": self.filter.kind.name(),
+            "filter_radius": self.filter.radius,
+            "shutter": self.shutter,
+        }).to_string()
+    }
+    fn from_json(line: &str) -> Result<Job, String> {
+        let data: Value = serde_json::from_str(line).map_err(|error| format!("invalid job {}", error))?;
+        let count = |key: &str| data[key].as_u64().map(|value| value as usize).ok_or(format!("job is missing {}", key));
+        let number = |key: &str| data[key].as_f64().map(|value| value as f32).ok_or(format!("job is missing {}", key));
+        Ok(Job {
+            scene: data["scene"].as_str().ok_or("job is missing scene")?.to_string(),
+            width: count("width")?,
+            height: count("height")?,
+            chunk: count("chunk")?,
+            samples: count("samples")?,
+            antialiasing: data["antialiasing"].as_bool().unwrap_or(true),
+            fov: number("fov")?,
+            settings: PathSettings::new(count("min_bounces")?, count("max_diffuse")?, count("max_specular")?, count("max_transmission")?),
+            filter: Filter::new(data["filter"].as_str().ok_or("job is missing filter")?, Some(number("filter_radius")?))?,
+            shutter: number("shutter")?,
+        })
+    }
+}
+
+//a tile of a frame, x and y count chunks
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Work {
+    frame: usize,
+    x: usize,
+    y: usize,
+}
+
+struct Shared {
+    job: String,
+    //length of every result, so a bad one is caught before anything is allocated for it
+    tile_size: usize,
+    queue: Mutex<VecDeque<Work>>,
+    //frame being rendered, tiles of any other that come back from lost workers aren't queued again
+    frame: AtomicUsize,
+    //set once every frame is done, connections then tell their workers to stop
+    finished: AtomicBool,
+}
+
+//hands out tiles to every worker that connects and puts their results together,
+//tiles from workers that drop out or stop answering are handed out again
+pub struct Coordinator {
+    //where it's listening, which has the real port when the address asked for port 0
+    pub address: SocketAddr,
+    job: Job,
+    shared: Arc<Shared>,
+    results: Receiver<(Work, Vec<u8>, u64)>,
+}
+
+impl Coordinator {
+    pub fn start(address: &str, job: Job) -> Coordinator {
+        let listener = TcpListener::bind(address).unwrap_or_else(|error| panic!("Could not listen on {}: {}", address, error));
+        let address = listener.local_addr().unwrap_or_else(|error| panic!("Could not listen on {}: {}", address, error));
+        let shared = Arc::new(Shared {
+            job: job.to_json(),
+            tile_size: Tile::new(0, 0, job.chunk, job.chunk, job.filter).encoded_size(),
+            queue: Mutex::new(VecDeque::new()),
+            frame: AtomicUsize::new(0),
+            finished: AtomicBool::new(false),
+        });
+        let (tx, rx) = mpsc::channel();
+        let accepting = shared.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let shared = accepting.clone();
+                let tx = tx.clone();
+                thread::spawn(move || serve(stream, shared, tx));
+            }
+        });
+        let coordinator = Coordinator {
+            address,
+            job,
+            shared,
+            results: rx,
+        };
+        println!("Waiting for workers on {}", coordinator.address);
+        coordinator
+    }
+
+    //waits until workers have sent back every tile of the frame
+    pub fn render(&self, frame: usize, preview: Option<&Preview>) -> (Image, Framebuffer) {
+        let (width, height, chunk) = (self.job.width, self.job.height, self.job.chunk);
+        let mut image = Image::new(width as u32, height as u32);
+        let mut framebuffer = Framebuffer::new(width, height);
+        let mut film = Tile::new(0, 0, width, height, self.job.filter);
+        let total = width / chunk * height / chunk;
+        {
+            let mut queue = self.shared.queue.lock().unwrap();
+            self.shared.frame.store(frame, Ordering::SeqCst);
+            queue.retain(|work| work.frame == frame);
+            for y in 0..height / chunk {
+                for x in 0..width / chunk {
+                    queue.push_back(Work { frame, x, y });
+                }
+            }
+        }
+        //a tile can come back twice if a worker that was given up on answers after all
+        let mut received = HashSet::new();
+        let start_time = Instant::now();
+        while received.len() < total {
+            let (work, bytes, rays) = self.results.recv().unwrap();
+            if work.frame != frame || !received.insert(work) {
+                continue;
+            }
+            let mut tile = Tile::new(work.x * chunk, work.y * chunk, chunk, chunk, self.job.filter);
+            tile.rays = rays;
+            if let Err(error) = tile.decode(&bytes) {
+                println!("\nBad tile {} {} from a worker, sending it out again: {}", work.x, work.y, error);
+                received.remove(&work);
+                self.shared.queue.lock().unwrap().push_back(work);
+                continue;
+            }
+            store_chunk(&mut image, &mut framebuffer, &mut film, &tile, preview);
+            status_print(received.len(), total, start_time.elapsed().as_secs());
+        }
+        (image, framebuffer)
+    }
+
+    pub fn finish(&self) {
+        self.shared.finished.store(true, Ordering::SeqCst);
+    }
+}
+
+fn next_work(shared: &Shared) -> Option<Work> {
+    loop {
+        if let Some(work) = shared.queue.lock().unwrap().pop_front() {
+            return Some(work);
+        }
+        if shared.finished.load(Ordering::SeqCst) {
+            return None;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+//one connection, which renders a tile at a time
+fn serve(stream: TcpStream, shared: Arc<Shared>, results: Sender<(Work, Vec<u8>, u64)>) {
+    let peer = stream.peer_addr().map_or("unknown".to_string(), |address| address.to_string());
+    let Ok(reading) = stream.try_clone() else {
+        return;
+    };
+    let mut reader = BufReader::new(reading);
+    let mut writer = stream;
+    let mut line = String::new();
+    if reader.read_line(&mut line).is_err() || line.trim() != "HELLO" || writeln!(writer, "{}", shared.job).is_err() {
+        return;
+    }
+    let _ = writer.set_read_timeout(Some(TILE_TIMEOUT));
+    while let Some(work) = next_work(&shared) {
+        match exchange(&mut reader, &mut writer, work, shared.tile_size) {
+            Ok((bytes, rays)) => {
+                if results.send((work, bytes, rays)).is_err() {
+                    return;
+                }
+            }
+            Err(error) => {
+                println!("\nLost worker {} ({}) on tile {} {}", peer, error, work.x, work.y);
+                //checked under the lock so a new frame can't start in between and be left with this tile
+                let mut queue = shared.queue.lock().unwrap();
+                if work.frame == shared.frame.load(Ordering::SeqCst) {
+                    queue.push_front(work);
+                }
+                return;
+            }
+        }
+    }
+    let _ = writeln!(writer, "DONE");
+}
+
+//sends a tile to render and waits for its sums, which must be tile_size bytes, and how many rays it took
+fn exchange(reader: &mut BufReader<TcpStream>, writer: &mut TcpStream, work: Work, tile_size: usize) -> Result<(Vec<u8>, u64), String> {
+    writeln!(writer, "TILE {} {} {}", work.frame, work.x, work.y).map_err(|error| error.to_string())?;
+    let mut line = String::new();
+    if reader.read_line(&mut line).map_err(|error| error.to_string())? == 0 {
+        return Err("connection closed".to_string());
+    }
+    let words: Vec<usize> = line.split_whitespace().skip(1).filter_map(|word| word.parse().ok()).collect();
+    let &[frame, x, y, length, rays] = words.as_slice() else {
+        return Err(format!("expected a result but got '{}'", line.trim()));
+    };
+    if !line.starts_with("RESULT") || (Work { frame, x, y }) != work {
+        return Err(format!("expected the result for tile {} {} but got '{}'", work.x, work.y, line.trim()));
+    }
+    if length != tile_size {
+        return Err(format!("expected {} bytes of tile but was told {}", tile_size, length));
+    }
+    let mut bytes = vec![0; length];
+    reader.read_exact(&mut bytes).map_err(|error| error.to_string())?;
+    Ok((bytes, rays as u64))
+}
+
+//the scene loaded for the frame the worker's threads are on, shared so it's only loaded once per frame
+struct WorkerScene {
+    data: Option<Value>,
+    frame: Option<usize>,
+    scene: Option<Scene>,
+    cache: SceneCache,
+}
+
+impl WorkerScene {
+    fn at_frame(&mut self, job: &Job, frame: usize) -> Result<Scene, String> {
+        if self.frame != Some(frame) {
+            let data = self.data.get_or_insert_with(|| scene_manager::read_scene_file(&job.scene));
+            let scene = Scene::at_frame(data, frame as f32, job.shutter, &mut self.cache).map_err(|error| error.to_string())?;
+            self.scene = Some(scene);
+            self.frame = Some(frame);
+        }
+        Ok(self.scene.clone().unwrap())
+    }
+}
+
+//connects threads connections to the coordinator and renders whatever tiles it sends until it says it's done
+pub fn work(address: &str, threads: usize) {
+    let scene = Arc::new(Mutex::new(WorkerScene { data: None, frame: None, scene: None, cache: SceneCache::new() }));
+    let handles: Vec<_> = (0..threads).map(|_| {
+        let address = address.to_string();
+        let scene = scene.clone();
+        thread::spawn(move || {
+            if let Err(error) = run_worker(&address, &scene) {
+                println!("Worker thread stopped: {}", error);
+            }
+        })
+    }).collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+fn run_worker(address: &str, scene: &Mutex<WorkerScene>) -> Result<(), String> {
+    let stream = TcpStream::connect(address).map_err(|error| format!("could not connect to {}: {}", address, error))?;
+    let mut reader = BufReader::new(stream.try_clone().map_err(|error| error.to_string())?);
+    let mut writer = stream;
+    writeln!(writer, "HELLO").map_err(|error| error.to_string())?;
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|error| error.to_string())?;
+    let job = Job::from_json(line.trim())?;
+    let filter = job.filter;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).map_err(|error| error.to_string())? == 0 || line.trim() == "DONE" {
+            return Ok(());
+        }
+        let words: Vec<usize> = line.split_whitespace().skip(1).filter_map(|word| word.parse().ok()).collect();
+        let &[frame, x, y] = words.as_slice() else {
+            return Err(format!("expected a tile but got '{}'", line.trim()));
+        };
+        let scene = scene.lock().unwrap().at_frame(&job, frame)?;
+        let (init_x, init_y) = (x * job.chunk, y * job.chunk);
+        let mut tile = Tile::new(init_x, init_y, job.chunk, job.chunk, filter);
+        for pixel_y in init_y..init_y + job.chunk {
+            for pixel_x in init_x..init_x + job.chunk {
+                scene.trace(pixel_x, pixel_y, &job.settings, job.samples, job.antialiasing, job.width, job.height, job.fov, &mut tile);
+            }
+        }
+        let bytes = tile.encode();
+        writeln!(writer, "RESULT {} {} {} {} {}", frame, x, y, bytes.len(), tile.rays).map_err(|error| error.to_string())?;
+        writer.write_all(&bytes).map_err(|error| error.to_string())?;
+        writer.flush().map_err(|error| error.to_string())?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //a connection that says hello and takes the job like a worker would, then does whatever the test wants
+    fn fake_worker(address: SocketAddr) -> (BufReader<TcpStream>, TcpStream) {
+        let stream = TcpStream::connect(address).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        writeln!(writer, "HELLO").unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        Job::from_json(line.trim()).unwrap();
+        (reader, writer)
+    }
+
+    fn next_tile(reader: &mut BufReader<TcpStream>) -> Vec<usize> {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with("TILE"), "expected a tile but got '{}'", line.trim());
+        line.split_whitespace().skip(1).map(|word| word.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn tiles_of_dropped_workers_are_rendered_by_the_others() {
+        let scene = std::env::temp_dir().join(format!("distributed_{}.json", std::process::id()));
+        std::fs::write(&scene, r#"{"camera": {"position": [0, 0, -3]},
+            "spheres": [{"center": [0, 0, 0], "radius": 1, "color": [0.8, 0.8, 0.8], "light": 1}],
+            "objects": []}"#).unwrap();
+        let coordinator = Coordinator::start("127.0.0.1:0", Job {
+            scene: scene.to_string_lossy().into_owned(),
+            width: 8,
+            height: 8,
+            chunk: 4,
+            samples: 2,
+            antialiasing: true,
+            fov: 1.5,
+            settings: PathSettings::new(1, 2, 2, 2),
+            filter: Filter::new("box", None).unwrap(),
+            shutter: 0.0,
+        });
+        let address = coordinator.address;
+
+        //both fakes connect before the frame starts, so each is sure to be handed one of the four tiles
+        let mut dropping = fake_worker(address);
+        let mut lying = fake_worker(address);
+        let helpers = thread::spawn(move || {
+            //one goes away partway through its tile
+            next_tile(&mut dropping.0);
+            drop(dropping);
+            //the other claims a result far bigger than a tile, which should end its connection
+            let tile = next_tile(&mut lying.0);
+            writeln!(lying.1, "RESULT {} {} {} {} 0", tile[0], tile[1], tile[2], 1u64 << 40).unwrap();
+            let mut rest = String::new();
+            assert_eq!(lying.0.read_line(&mut rest).unwrap_or(0), 0, "the coordinator kept talking after a bad length");
+            //then two real workers pick up what's left
+            let workers: Vec<_> = (0..2).map(|_| thread::spawn(move || work(&address.to_string(), 1))).collect();
+            for worker in workers {
+                worker.join().unwrap();
+            }
+        });
+
+        let (_, framebuffer) = coordinator.render(0, None);
+        coordinator.finish();
+        helpers.join().unwrap();
+        std::fs::remove_file(&scene).unwrap();
+        assert!(framebuffer.pixels.iter().all(|pixel| pixel.samples > 0), "some pixels were never rendered");
+    }
+}
@@ -0,0 +1,295 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+use rand::prelude::*;
+use super::lights::xyz_to_linear_srgb;
+use super::objects::{cross_product, dot_product, normalize};
+use super::texture_manager::Texture;
+
+//light arriving from infinitely far away for rays that miss everything
+#[derive(Clone)]
+pub enum Environment {
+    Black,
+    Constant { color: [f32; 3] },
+    //blends from bottom straight down to top straight up
+    Gradient { top: [f32; 3], bottom: [f32; 3] },
+    //latitude-longitude image, u = 0.5 looks down +z before rotating
+    Map { texture: Arc<Texture>, rotation: f32, intensity: f32, distribution: Arc<Distribution> },
+    Sky(Sky),
+}
+
+//where a light sample came from, pdf is per unit solid angle
+pub struct LightSample {
+    pub direction: [f32; 3],
+    pub radiance: [f32; 3],
+    pub pdf: f32,
+}
+
+impl Environment {
+    //rotation in degrees around the y axis
+    pub fn map(texture: Arc<Texture>, rotation: f32, intensity: f32) -> Environment {
+        let distribution = Arc::new(Distribution::new(&texture));
+        Environment::Map {
+            texture,
+            rotation: rotation.to_radians(),
+            intensity,
+            distribution,
+        }
+    }
+
+    pub fn radiance(&self, direction: [f32; 3]) -> [f32; 3] {
+        match self {
+            Environment::Black => [0.0, 0.0, 0.0],
+            Environment::Constant { color } => *color,
+            Environment::Gradient { top, bottom } => {
+                let length = (direction[0].powi(2) + direction[1].powi(2) + direction[2].powi(2)).sqrt();
+                let blend = 0.5 + 0.5 * direction[1] / length;
+                [0, 1, 2].map(|i| bottom[i] * (1.0 - blend) + top[i] * blend)
+            }
+            Environment::Map { texture, rotation, intensity, .. } => {
+                texture.sample(direction_to_uv(direction, *rotation)).map(|value| value * intensity)
+            }
+            Environment::Sky(sky) => sky.radiance(direction),
+        }
+    }
+
+    //only image maps and the sun are bright and uneven enough to be worth aiming shadow rays at
+    pub fn sample(&self, rng: &mut ThreadRng) -> Option<LightSample> {
+        let (rotation, distribution) = match self {
+            Environment::Map { rotation, distribution, .. } => (rotation, distribution),
+            Environment::Sky(sky) => {
+                let direction = sky.sample_sun(rng);
+                return Some(LightSample {
+                    direction,
+                    radiance: sky.radiance(direction),
+                    pdf: 1.0 / sky.sun_solid_angle,
+                });
+            }
+            _ => return None,
+        };
+        let (uv, uv_pdf) = distribution.sample(rng);
+        let direction = uv_to_direction(uv, *rotation);
+        let pdf = uv_to_solid_angle(uv_pdf, direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            radiance: self.radiance(direction),
+            pdf,
+        })
+    }
+
+    //chance per unit solid angle that sample picks this direction
+    pub fn pdf(&self, direction: [f32; 3]) -> f32 {
+        match self {
+            Environment::Map { rotation, distribution, .. } => {
+                let uv = direction_to_uv(direction, *rotation);
+                uv_to_solid_angle(distribution.pdf(uv), direction)
+            }
+            Environment::Sky(sky) if sky.in_sun(direction) => 1.0 / sky.sun_solid_angle,
+            _ => 0.0,
+        }
+    }
+}
+
+fn direction_to_uv(direction: [f32; 3], rotation: f32) -> [f32; 2] {
+    let length = (direction[0].powi(2) + direction[1].powi(2) + direction[2].powi(2)).sqrt();
+    let u = 0.5 + (direction[0].atan2(direction[2]) - rotation) / (2.0 * PI);
+    [u.rem_euclid(1.0), 0.5 + (direction[1] / length).clamp(-1.0, 1.0).asin() / PI]
+}
+
+fn uv_to_direction(uv: [f32; 2], rotation: f32) -> [f32; 3] {
+    let longitude = (uv[0] - 0.5) * 2.0 * PI + rotation;
+    let latitude = (uv[1] - 0.5) * PI;
+    [latitude.cos() * longitude.sin(), latitude.sin(), latitude.cos() * longitude.cos()]
+}
+
+//the image is stretched over 2 pi by pi radians, squeezed by cos(latitude) towards the poles
+fn uv_to_solid_angle(uv_pdf: f32, direction: [f32; 3]) -> f32 {
+    let length = (direction[0].powi(2) + direction[1].powi(2) + direction[2].powi(2)).sqrt();
+    let cos_latitude = (1.0 - (direction[1] / length).powi(2)).max(0.0).sqrt();
+    if cos_latitude <= 0.0 {
+        return 0.0;
+    }
+    uv_pdf / (2.0 * PI * PI * cos_latitude)
+}
+
+//picks texels in proportion to how much light they send, rows first then a texel in the row
+pub struct Distribution {
+    width: usize,
+    height: usize,
+    //running totals, row_totals[y] is everything up to and including row y
+    row_totals: Vec<f32>,
+    //running totals along each row
+    columns: Vec<f32>,
+    weights: Vec<f32>,
+}
+
+impl Distribution {
+    pub fn new(texture: &Texture) -> Distribution {
+        let (width, height) = (texture.width, texture.height);
+        let mut columns = Vec::with_capacity(width * height);
+        let mut weights = Vec::with_capacity(width * height);
+        let mut row_totals = Vec::with_capacity(height);
+        let mut total = 0.0;
+        for y in 0..height {
+            //rows near the poles cover less of the sphere
+            let latitude = ((y as f32 + 0.5) / height as f32 - 0.5) * PI;
+            let mut row = 0.0;
+            for x in 0..width {
+                let color = texture.data[y * width + x];
+                let weight = (0.2126 * color[0] + 0.7152 * color[1] + 0.0722 * color[2]) * latitude.cos();
+                row += weight;
+                columns.push(row);
+                weights.push(weight);
+            }
+            total += row;
+            row_totals.push(total);
+        }
+        Distribution {
+            width,
+            height,
+            row_totals,
+            columns,
+            weights,
+        }
+    }
+
+    fn sample(&self, rng: &mut ThreadRng) -> ([f32; 2], f32) {
+        let total = self.row_totals[self.height - 1];
+        if total <= 0.0 {
+            return ([rng.gen(), rng.gen()], 1.0);
+        }
+        let pick = rng.gen::<f32>() * total;
+        let y = self.row_totals.partition_point(|&running| running <= pick).min(self.height - 1);
+        let row = &self.columns[y * self.width..(y + 1) * self.width];
+        let pick = rng.gen::<f32>() * row[self.width - 1];
+        let x = row.partition_point(|&running| running <= pick).min(self.width - 1);
+        //row 0 is the top of the image, where v is 1
+        let uv = [(x as f32 + rng.gen::<f32>()) / self.width as f32, 1.0 - (y as f32 + rng.gen::<f32>()) / self.height as f32];
+        (uv, self.texel_pdf(x, y))
+    }
+
+    fn pdf(&self, uv: [f32; 2]) -> f32 {
+        if self.row_totals[self.height - 1] <= 0.0 {
+            return 1.0;
+        }
+        let x = ((uv[0] * self.width as f32) as usize).min(self.width - 1);
+        let y = (((1.0 - uv[1]) * self.height as f32) as usize).min(self.height - 1);
+        self.texel_pdf(x, y)
+    }
+
+    //density over the unit uv square of landing in this texel
+    fn texel_pdf(&self, x: usize, y: usize) -> f32 {
+        self.weights[y * self.width + x] / self.row_totals[self.height - 1] * (self.width * self.height) as f32
+    }
+}
+
+//scene units per kcd/m^2 of sky luminance, puts a midday zenith at around a third
+const SKY_SCALE: f32 = 0.04;
+//irradiance from a midday sun through a clear sky, in scene units
+const SUN_IRRADIANCE: f32 = 3.0;
+
+//preetham, shirley and smits' analytic daylight model with a sun disk on top
+#[derive(Clone)]
+pub struct Sky {
+    pub sun_direction: [f32; 3],
+    pub intensity: f32,
+    //zenith luminance and chromaticity, then the perez coefficients for each
+    zenith: [f32; 3],
+    coefficients: [[f32; 5]; 3],
+    sun_theta: f32,
+    sun_radiance: [f32; 3],
+    cos_sun_radius: f32,
+    pub sun_solid_angle: f32,
+}
+
+impl Sky {
+    //elevation above the horizon and azimuth from +z towards +x in degrees, sun_size is the disk's diameter in degrees,
+    //turbidity runs from about 2 for a clear sky to 10 for haze
+    pub fn new(elevation: f32, azimuth: f32, turbidity: f32, intensity: f32, sun_size: f32, sun_intensity: f32) -> Sky {
+        let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+        let sun_direction = [elevation.cos() * azimuth.sin(), elevation.sin(), elevation.cos() * azimuth.cos()];
+        let t = turbidity;
+        //the sky model breaks down once the sun is below the horizon
+        let theta = (PI / 2.0 - elevation).min(PI / 2.0);
+        let powers = [theta.powi(3), theta.powi(2), theta, 1.0];
+        let polynomial = |row: [f32; 4]| row.iter().zip(powers).map(|(a, b)| a * b).sum::<f32>();
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta);
+        let zenith_luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let zenith_x = t * t * polynomial([0.00166, -0.00375, 0.00209, 0.0])
+            + t * polynomial([-0.02903, 0.06377, -0.03202, 0.00394])
+            + polynomial([0.11693, -0.21196, 0.06052, 0.25886]);
+        let zenith_y = t * t * polynomial([0.00275, -0.00610, 0.00317, 0.0])
+            + t * polynomial([-0.04214, 0.08970, -0.04153, 0.00516])
+            + polynomial([0.15346, -0.26756, 0.06670, 0.26688]);
+        let coefficients = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+
+        //light from a low sun passes through more air, which takes out more blue, and more again in haze
+        let sun_theta_degrees = theta.to_degrees();
+        let air_mass = 1.0 / (theta.cos() + 0.15 * (93.885 - sun_theta_degrees).max(0.1).powf(-1.253));
+        let extinction = [0.08, 0.13, 0.27].map(|rate| rate * (0.5 + t / 4.0));
+        let transmittance = extinction.map(|rate| (-rate * air_mass).exp());
+
+        let radius = (sun_size / 2.0).to_radians();
+        //1 - cos written with sin to keep precision for such a small disk
+        let sun_solid_angle = 2.0 * PI * 2.0 * (radius / 2.0).sin().powi(2);
+        let sun_radiance = transmittance.map(|value| value * SUN_IRRADIANCE * sun_intensity * intensity / sun_solid_angle);
+        Sky {
+            sun_direction,
+            intensity,
+            zenith: [zenith_luminance, zenith_x, zenith_y],
+            coefficients,
+            sun_theta: theta,
+            sun_radiance: if elevation > -radius { sun_radiance } else { [0.0; 3] },
+            cos_sun_radius: radius.cos(),
+            sun_solid_angle,
+        }
+    }
+
+    fn in_sun(&self, direction: [f32; 3]) -> bool {
+        dot_product(normalize(direction), self.sun_direction) >= self.cos_sun_radius
+    }
+
+    pub fn radiance(&self, direction: [f32; 3]) -> [f32; 3] {
+        let direction = normalize(direction);
+        //below the horizon shows the sky just above it
+        let cos_theta = direction[1].max(0.01);
+        let cos_gamma = dot_product(direction, self.sun_direction).clamp(-1.0, 1.0);
+        let gamma = cos_gamma.acos();
+        let perez = |[a, b, c, d, e]: [f32; 5], cos_theta: f32, gamma: f32| {
+            (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+        };
+        let [luminance, x, y] = [0, 1, 2].map(|i| {
+            let coefficients = self.coefficients[i];
+            self.zenith[i] * perez(coefficients, cos_theta, gamma) / perez(coefficients, 1.0, self.sun_theta)
+        });
+
+        //yxy to xyz to linear srgb
+        let luminance = luminance.max(0.0) * SKY_SCALE * self.intensity;
+        let big_x = x / y * luminance;
+        let big_z = (1.0 - x - y) / y * luminance;
+        let mut color = xyz_to_linear_srgb([big_x, luminance, big_z]).map(|value| value.max(0.0));
+        if direction[1] >= 0.0 && cos_gamma >= self.cos_sun_radius {
+            color = [0, 1, 2].map(|i| color[i] + self.sun_radiance[i]);
+        }
+        color
+    }
+
+    //uniform direction inside the sun's cone
+    fn sample_sun(&self, rng: &mut ThreadRng) -> [f32; 3] {
+        let cos = 1.0 - rng.gen::<f32>() * (1.0 - self.cos_sun_radius);
+        let sin = (1.0 - cos * cos).max(0.0).sqrt();
+        let angle = 2.0 * PI * rng.gen::<f32>();
+        let w = self.sun_direction;
+        let axis = if w[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+        let u = normalize(cross_product(axis, w));
+        let v = cross_product(w, u);
+        [0, 1, 2].map(|i| u[i] * sin * angle.cos() + v[i] * sin * angle.sin() + w[i] * cos)
+    }
+}
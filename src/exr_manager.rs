@@ -0,0 +1,97 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use super::framebuffer::{Framebuffer, Pixel};
+
+//a channel name and how to read it from a pixel
+type Channel = (&'static str, fn(&Pixel) -> f32);
+
+//every pass as its own float channel, compositors group them into layers by the part before the dot
+fn channels() -> Vec<Channel> {
+    let mut channels: Vec<Channel> = vec![
+        ("R", |pixel| pixel.color[0]),
+        ("G", |pixel| pixel.color[1]),
+        ("B", |pixel| pixel.color[2]),
+        ("Z", |pixel| pixel.depth),
+        ("emission.R", |pixel| pixel.emission[0]),
+        ("emission.G", |pixel| pixel.emission[1]),
+        ("emission.B", |pixel| pixel.emission[2]),
+        ("direct.R", |pixel| pixel.direct[0]),
+        ("direct.G", |pixel| pixel.direct[1]),
+        ("direct.B", |pixel| pixel.direct[2]),
+        ("indirect.R", |pixel| pixel.indirect[0]),
+        ("indirect.G", |pixel| pixel.indirect[1]),
+        ("indirect.B", |pixel| pixel.indirect[2]),
+        ("albedo.R", |pixel| pixel.albedo[0]),
+        ("albedo.G", |pixel| pixel.albedo[1]),
+        ("albedo.B", |pixel| pixel.albedo[2]),
+        ("normal.X", |pixel| pixel.normal[0]),
+        ("normal.Y", |pixel| pixel.normal[1]),
+        ("normal.Z", |pixel| pixel.normal[2]),
+        ("id.object", |pixel| pixel.object as f32),
+        ("id.material", |pixel| pixel.material as f32),
+        ("samples.Y", |pixel| pixel.samples as f32),
+        ("variance.Y", |pixel| pixel.variance),
+    ];
+    //exr wants the channel list and the data in each line in name order
+    channels.sort_by_key(|(name, _)| *name);
+    channels
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend(name.as_bytes());
+    header.push(0);
+    header.extend(kind.as_bytes());
+    header.push(0);
+    header.extend((value.len() as i32).to_le_bytes());
+    header.extend(value);
+}
+
+//single part, uncompressed, one scanline per block with every channel as 32 bit float
+pub fn save(framebuffer: &Framebuffer, filename: &str) -> std::io::Result<()> {
+    let channels = channels();
+    let (width, height) = (framebuffer.width, framebuffer.height);
+
+    let mut header = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+    let mut list = vec![];
+    for (name, _) in &channels {
+        list.extend(name.as_bytes());
+        list.push(0);
+        //pixel type float, not linear, 3 reserved bytes, no subsampling
+        list.extend(2_i32.to_le_bytes());
+        list.extend([0, 0, 0, 0]);
+        list.extend(1_i32.to_le_bytes());
+        list.extend(1_i32.to_le_bytes());
+    }
+    list.push(0);
+    attribute(&mut header, "channels", "chlist", &list);
+    attribute(&mut header, "compression", "compression", &[0]);
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1].iter().flat_map(|value| value.to_le_bytes()).collect();
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut header, "pixelAspectRatio", "float", &1.0_f32.to_le_bytes());
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0.0_f32.to_le_bytes(), 0.0_f32.to_le_bytes()].concat());
+    attribute(&mut header, "screenWindowWidth", "float", &1.0_f32.to_le_bytes());
+    header.push(0);
+
+    //each block is its y, its size, then the line for every channel in turn
+    let line_size = width * channels.len() * 4;
+    let block_size = 8 + line_size;
+    let table_end = header.len() + height * 8;
+    let mut file = BufWriter::new(File::create(filename)?);
+    file.write_all(&header)?;
+    for y in 0..height {
+        file.write_all(&((table_end + y * block_size) as u64).to_le_bytes())?;
+    }
+    for y in 0..height {
+        file.write_all(&(y as i32).to_le_bytes())?;
+        file.write_all(&(line_size as i32).to_le_bytes())?;
+        let row = &framebuffer.pixels[y * width..(y + 1) * width];
+        for (_, value) in &channels {
+            for pixel in row {
+                file.write_all(&value(pixel).to_le_bytes())?;
+            }
+        }
+    }
+    file.flush()
+}
@@ -0,0 +1,147 @@
+use std::f32::consts::PI;
+
+#[derive(Clone, Copy)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    //b = c = 1/3, a little blur and a little ringing
+    Mitchell,
+    //windowed sinc with as many lobes as the radius
+    Lanczos,
+}
+
+impl FilterKind {
+    pub fn from_name(name: &str) -> Option<FilterKind> {
+        match name {
+            "box" => Some(FilterKind::Box),
+            "tent" => Some(FilterKind::Tent),
+            "gaussian" => Some(FilterKind::Gaussian),
+            "mitchell" => Some(FilterKind::Mitchell),
+            "lanczos" => Some(FilterKind::Lanczos),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::Lanczos => "lanczos",
+        }
+    }
+    //box at 0.5 keeps each sample to its own pixel, the others reach as far as they are usually drawn
+    pub fn default_radius(&self) -> f32 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+}
+
+//how much a sample counts towards the pixels around it, radius is in pixels and the filter is 0 from there out
+#[derive(Clone, Copy)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: f32,
+    //makes the weights integrate to 1 across one axis
+    pub scale: f32,
+}
+
+impl Filter {
+    //no radius uses the filter's default one
+    pub fn new(name: &str, radius: Option<f32>) -> Result<Filter, String> {
+        let kind = FilterKind::from_name(name).ok_or(format!("Unknown filter {}, expected box, tent, gaussian, mitchell or lanczos", name))?;
+        let radius = radius.unwrap_or(kind.default_radius());
+        if radius <= 0.0 || !radius.is_finite() {
+            return Err(format!("Filter radius must be positive, not {}", radius));
+        }
+        let mut filter = Filter { kind, radius, scale: 1.0 };
+        //midpoint rule, fine enough that the ends cut off by the radius don't matter
+        let steps = 1000;
+        let step = 2.0 * radius / steps as f32;
+        let integral: f32 = (0..steps).map(|i| filter.evaluate(-radius + (i as f32 + 0.5) * step) * step).sum();
+        filter.scale = 1.0 / integral;
+        Ok(filter)
+    }
+
+    //weight of a sample offset by dx, dy from the centre of a pixel
+    pub fn weight(&self, dx: f32, dy: f32) -> f32 {
+        self.evaluate(dx) * self.evaluate(dy) * self.scale * self.scale
+    }
+
+    fn evaluate(&self, x: f32) -> f32 {
+        let x = x.abs();
+        if x >= self.radius {
+            return 0.0;
+        }
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => 1.0 - x / self.radius,
+            FilterKind::Gaussian => {
+                //the tail past 3 standard deviations is cut off, shifted down so it reaches 0 at the radius
+                let sigma = self.radius / 3.0;
+                let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+                gaussian(x) - gaussian(self.radius)
+            }
+            FilterKind::Mitchell => {
+                //the usual filter is 2 wide, stretched to the radius
+                let x = 2.0 * x / self.radius;
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3) + (-18.0 + 12.0 * b + 6.0 * c) * x.powi(2) + (6.0 - 2.0 * b)) / 6.0
+                } else {
+                    ((-b - 6.0 * c) * x.powi(3) + (6.0 * b + 30.0 * c) * x.powi(2) + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+                }
+            }
+            FilterKind::Lanczos => sinc(x) * sinc(x / self.radius),
+        }
+    }
+
+    //how many pixels past its own a sample can reach
+    pub fn margin(&self) -> usize {
+        (self.radius - 0.5).ceil().max(0.0) as usize
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_integrate_to_one() {
+        for name in ["box", "tent", "gaussian", "mitchell", "lanczos"] {
+            let filter = Filter::new(name, None).unwrap();
+            //midpoints of a grid offset from the one the scale was worked out on
+            let steps = 300;
+            let step = 2.0 * filter.radius / steps as f32;
+            let mut integral = 0.0;
+            for y in 0..steps {
+                for x in 0..steps {
+                    let position = |i: usize| -filter.radius + (i as f32 + 0.5) * step;
+                    integral += filter.weight(position(x), position(y)) * step * step;
+                }
+            }
+            assert!((integral - 1.0).abs() < 0.01, "{} integrates to {}", name, integral);
+        }
+    }
+
+    #[test]
+    fn bad_names_and_radii_are_errors() {
+        assert!(Filter::new("blackman", None).is_err());
+        assert!(Filter::new("tent", Some(0.0)).is_err());
+        assert!(Filter::new("tent", Some(-1.0)).is_err());
+        assert_eq!(Filter::new("lanczos", Some(2.0)).unwrap().radius, 2.0);
+    }
+}
@@ -0,0 +1,346 @@
+use super::filters::Filter;
+
+//everything worked out for one pixel, light is averaged over its samples
+#[derive(Clone, Copy, Default)]
+pub struct Pixel {
+    pub color: [f32; 3],
+    //color split by how many times the light scattered before reaching the camera, none, once, or more
+    pub emission: [f32; 3],
+    pub direct: [f32; 3],
+    pub indirect: [f32; 3],
+    //surface first seen through the pixel, averaged over the samples that saw one, depth is the distance to it
+    pub albedo: [f32; 3],
+    pub normal: [f32; 3],
+    pub depth: f32,
+    //ids from the sample with the largest filter weight out of those that hit something, 0 where none did
+    pub object: u32,
+    pub material: u32,
+    pub samples: u32,
+    //how far the luminance of color could be off from the true value, squared
+    pub variance: f32,
+}
+
+impl Pixel {
+    //the color as it goes into a png, anything brighter than 1 is clipped
+    pub fn to_rgba(self) -> [u8; 4] {
+        [
+            (self.color[0] * 255.0) as u8,
+            (self.color[1] * 255.0) as u8,
+            (self.color[2] * 255.0) as u8,
+            255,
+        ]
+    }
+}
+
+pub fn luminance(color: [f32; 3]) -> f32 {
+    0.2126 * color[0] + 0.7152 * color[1] + 0.0722 * color[2]
+}
+
+//one camera sample, position is where it went through the image in pixels from the top left corner
+pub struct Sample {
+    pub position: [f32; 2],
+    pub emission: [f32; 3],
+    pub direct: [f32; 3],
+    pub indirect: [f32; 3],
+    //first thing it hit, None when it went straight out of the scene
+    pub surface: Option<Surface>,
+}
+
+pub struct Surface {
+    pub albedo: [f32; 3],
+    pub normal: [f32; 3],
+    pub depth: f32,
+    pub object: u32,
+    pub material: u32,
+}
+
+//bytes each pixel's sums take up when a tile is sent over the network, 23 floats and 3 ids
+const ACCUMULATED_SIZE: usize = 26 * 4;
+
+//running sums of every sample that reached a pixel, each scaled by the filter
+#[derive(Clone, Copy, Default)]
+pub struct Accumulated {
+    weight: f32,
+    weight_squares: f32,
+    //albedo, normal and depth only use the positive part of the filter, ringing in them would throw off the denoiser
+    feature_weight: f32,
+    surface_weight: f32,
+    //ids come from the sample that counts the most
+    id_weight: f32,
+    emission: [f32; 3],
+    direct: [f32; 3],
+    indirect: [f32; 3],
+    luminance: f32,
+    luminance_squares: f32,
+    albedo: [f32; 3],
+    normal: [f32; 3],
+    depth: f32,
+    object: u32,
+    material: u32,
+    samples: u32,
+}
+
+impl Accumulated {
+    fn add(&mut self, sample: &Sample, weight: f32) {
+        let add = |sum: &mut [f32; 3], value: [f32; 3]| *sum = [0, 1, 2].map(|i| sum[i] + value[i] * weight);
+        add(&mut self.emission, sample.emission);
+        add(&mut self.direct, sample.direct);
+        add(&mut self.indirect, sample.indirect);
+        let luminance = luminance([0, 1, 2].map(|i| sample.emission[i] + sample.direct[i] + sample.indirect[i]));
+        self.luminance += luminance * weight;
+        self.luminance_squares += luminance * luminance * weight;
+        self.weight += weight;
+        self.weight_squares += weight * weight;
+        let feature_weight = weight.max(0.0);
+        self.feature_weight += feature_weight;
+        if let Some(surface) = &sample.surface {
+            let add = |sum: &mut [f32; 3], value: [f32; 3]| *sum = [0, 1, 2].map(|i| sum[i] + value[i] * feature_weight);
+            add(&mut self.albedo, surface.albedo);
+            add(&mut self.normal, surface.normal);
+            self.depth += surface.depth * feature_weight;
+            self.surface_weight += feature_weight;
+            if weight > self.id_weight {
+                self.id_weight = weight;
+                self.object = surface.object;
+                self.material = surface.material;
+            }
+        }
+    }
+
+    fn merge(&mut self, other: &Accumulated) {
+        let add = |sum: &mut [f32; 3], value: [f32; 3]| *sum = [0, 1, 2].map(|i| sum[i] + value[i]);
+        add(&mut self.emission, other.emission);
+        add(&mut self.direct, other.direct);
+        add(&mut self.indirect, other.indirect);
+        add(&mut self.albedo, other.albedo);
+        add(&mut self.normal, other.normal);
+        self.luminance += other.luminance;
+        self.luminance_squares += other.luminance_squares;
+        self.weight += other.weight;
+        self.weight_squares += other.weight_squares;
+        self.feature_weight += other.feature_weight;
+        self.depth += other.depth;
+        self.surface_weight += other.surface_weight;
+        self.samples += other.samples;
+        if other.id_weight > self.id_weight {
+            self.id_weight = other.id_weight;
+            self.object = other.object;
+            self.material = other.material;
+        }
+    }
+
+    //the floats then the ids, little endian
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        let floats = [
+            [self.weight, self.weight_squares, self.feature_weight, self.surface_weight, self.id_weight].as_slice(),
+            &self.emission,
+            &self.direct,
+            &self.indirect,
+            &[self.luminance, self.luminance_squares],
+            &self.albedo,
+            &self.normal,
+            &[self.depth],
+        ].concat();
+        for value in floats {
+            bytes.extend(value.to_le_bytes());
+        }
+        for value in [self.object, self.material, self.samples] {
+            bytes.extend(value.to_le_bytes());
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Accumulated {
+        let word = |index: usize| [bytes[index * 4], bytes[index * 4 + 1], bytes[index * 4 + 2], bytes[index * 4 + 3]];
+        let float = |index: usize| f32::from_le_bytes(word(index));
+        let vector = |index: usize| [float(index), float(index + 1), float(index + 2)];
+        Accumulated {
+            weight: float(0),
+            weight_squares: float(1),
+            feature_weight: float(2),
+            surface_weight: float(3),
+            id_weight: float(4),
+            emission: vector(5),
+            direct: vector(8),
+            indirect: vector(11),
+            luminance: float(14),
+            luminance_squares: float(15),
+            albedo: vector(16),
+            normal: vector(19),
+            depth: float(22),
+            object: u32::from_le_bytes(word(23)),
+            material: u32::from_le_bytes(word(24)),
+            samples: u32::from_le_bytes(word(25)),
+        }
+    }
+
+    pub fn resolve(&self) -> Pixel {
+        let mut pixel = Pixel { samples: self.samples, object: self.object, material: self.material, ..Pixel::default() };
+        if self.weight.abs() < 1e-8 {
+            return pixel;
+        }
+        //negative lobes can take a pass below 0 next to a bright edge
+        let average = |sum: [f32; 3]| sum.map(|value| (value / self.weight).max(0.0));
+        pixel.emission = average(self.emission);
+        pixel.direct = average(self.direct);
+        pixel.indirect = average(self.indirect);
+        pixel.color = [0, 1, 2].map(|i| pixel.emission[i] + pixel.direct[i] + pixel.indirect[i]);
+        if self.feature_weight > 1e-8 {
+            pixel.albedo = self.albedo.map(|value| value / self.feature_weight);
+            pixel.normal = self.normal.map(|value| value / self.feature_weight);
+        }
+        if self.surface_weight > 1e-8 {
+            pixel.depth = self.depth / self.surface_weight;
+        }
+        //spread of the weighted mean rather than of single samples, so it shrinks as samples go up
+        let mean = self.luminance / self.weight;
+        let spread = (self.luminance_squares / self.weight - mean * mean).max(0.0);
+        pixel.variance = spread * self.weight_squares / (self.weight * self.weight);
+        pixel
+    }
+}
+
+//a block of pixels with a border as wide as the filter reaches, so samples near its edge still land on the pixels next door
+pub struct Tile {
+    pub x: i64,
+    pub y: i64,
+    pub width: usize,
+    pub height: usize,
+    pub filter: Filter,
+    pub cells: Vec<Accumulated>,
+    //rays traced into the scene to fill it, shadow rays included
+    pub rays: u64,
+}
+
+impl Tile {
+    //x, y, width and height are the block without the border
+    pub fn new(x: usize, y: usize, width: usize, height: usize, filter: Filter) -> Tile {
+        let margin = filter.margin();
+        Tile {
+            x: x as i64 - margin as i64,
+            y: y as i64 - margin as i64,
+            width: width + 2 * margin,
+            height: height + 2 * margin,
+            filter,
+            cells: vec![Accumulated::default(); (width + 2 * margin) * (height + 2 * margin)],
+            rays: 0,
+        }
+    }
+
+    fn cell(&mut self, x: i64, y: i64) -> Option<&mut Accumulated> {
+        let (local_x, local_y) = (x - self.x, y - self.y);
+        if local_x < 0 || local_y < 0 || local_x >= self.width as i64 || local_y >= self.height as i64 {
+            return None;
+        }
+        self.cells.get_mut(local_y as usize * self.width + local_x as usize)
+    }
+
+    pub fn splat(&mut self, sample: &Sample) {
+        let [sample_x, sample_y] = sample.position;
+        let radius = self.filter.radius;
+        if let Some(cell) = self.cell(sample_x.floor() as i64, sample_y.floor() as i64) {
+            cell.samples += 1;
+        }
+        for y in (sample_y - radius - 0.5).floor() as i64..=(sample_y + radius - 0.5).ceil() as i64 {
+            for x in (sample_x - radius - 0.5).floor() as i64..=(sample_x + radius - 0.5).ceil() as i64 {
+                let weight = self.filter.weight(x as f32 + 0.5 - sample_x, y as f32 + 0.5 - sample_y);
+                if weight == 0.0 {
+                    continue;
+                }
+                if let Some(cell) = self.cell(x, y) {
+                    cell.add(sample, weight);
+                }
+            }
+        }
+    }
+
+    //adds in everything another tile collected where the two overlap
+    pub fn merge(&mut self, other: &Tile) {
+        self.rays += other.rays;
+        for local_y in 0..other.height {
+            for local_x in 0..other.width {
+                let x = other.x + local_x as i64;
+                let y = other.y + local_y as i64;
+                if let Some(cell) = self.cell(x, y) {
+                    cell.merge(&other.cells[local_y * other.width + local_x]);
+                }
+            }
+        }
+    }
+
+    //how many bytes encode gives
+    pub fn encoded_size(&self) -> usize {
+        self.cells.len() * ACCUMULATED_SIZE
+    }
+
+    //every cell, for sending to another machine
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.encoded_size());
+        for cell in &self.cells {
+            cell.encode(&mut bytes);
+        }
+        bytes
+    }
+
+    //fills in the cells from what encode gave for a tile of the same size
+    pub fn decode(&mut self, bytes: &[u8]) -> Result<(), String> {
+        if bytes.len() != self.encoded_size() {
+            return Err(format!("expected {} bytes of tile but got {}", self.encoded_size(), bytes.len()));
+        }
+        for (cell, chunk) in self.cells.iter_mut().zip(bytes.chunks_exact(ACCUMULATED_SIZE)) {
+            *cell = Accumulated::decode(chunk);
+        }
+        Ok(())
+    }
+
+    pub fn resolve(&self, x: usize, y: usize) -> Pixel {
+        let local_x = (x as i64 - self.x) as usize;
+        let local_y = (y as i64 - self.y) as usize;
+        self.cells[local_y * self.width + local_x].resolve()
+    }
+}
+
+//the whole image in floats, top row first
+#[derive(Clone)]
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Pixel>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Pixel::default(); width * height],
+        }
+    }
+    pub fn set_pixel(&mut self, x: usize, y: usize, pixel: Pixel) {
+        self.pixels[y * self.width + x] = pixel;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_near_an_edge_reach_the_next_tile() {
+        let filter = Filter::new("tent", None).unwrap();
+        let mut tile = Tile::new(0, 0, 4, 4, filter);
+        tile.splat(&Sample {
+            position: [3.9, 1.5],
+            emission: [1.0, 1.0, 1.0],
+            direct: [0.0; 3],
+            indirect: [0.0; 3],
+            surface: None,
+        });
+        //pixel 4 belongs to the tile to the right and is only in this one's margin
+        assert_eq!(tile.resolve(4, 1).color, [1.0, 1.0, 1.0]);
+        let mut film = Tile::new(0, 0, 8, 4, filter);
+        film.merge(&tile);
+        assert_eq!(film.resolve(4, 1).color, [1.0, 1.0, 1.0]);
+        assert_eq!(film.resolve(3, 1).color, [1.0, 1.0, 1.0]);
+        assert_eq!(film.resolve(5, 1).color, [0.0; 3]);
+    }
+}
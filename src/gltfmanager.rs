@@ -0,0 +1,577 @@
+use std::f32::consts::PI;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use serde_json::Value;
+use super::lights::Light;
+use super::objects::{self, cross_product, dot_product, normalize};
+use super::objmanager::{MeshError, Placement};
+use super::texture_manager::{ColorSpace, Texture, TextureCache, WrapMode};
+
+//column major like gltf, matrix[column][row]
+type Matrix = [[f32; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+pub struct GltfScene {
+    pub triangles: Vec<objects::Triangle>,
+    pub lights: Vec<Light>,
+    pub camera: Option<objects::Camera>,
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut result = [[0.0; 4]; 4];
+    for (column, result_column) in result.iter_mut().enumerate() {
+        for (row, value) in result_column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b[column][k]).sum();
+        }
+    }
+    result
+}
+
+fn transform_point(matrix: &Matrix, point: [f32; 3]) -> [f32; 3] {
+    let mut result = [0.0; 3];
+    for (row, value) in result.iter_mut().enumerate() {
+        *value = matrix[0][row] * point[0] + matrix[1][row] * point[1] + matrix[2][row] * point[2] + matrix[3][row];
+    }
+    result
+}
+
+fn transform_direction(matrix: &Matrix, direction: [f32; 3]) -> [f32; 3] {
+    let mut result = [0.0; 3];
+    for (row, value) in result.iter_mut().enumerate() {
+        *value = matrix[0][row] * direction[0] + matrix[1][row] * direction[1] + matrix[2][row] * direction[2];
+    }
+    result
+}
+
+//normals use the inverse transpose, which is the cofactor matrix up to the sign of the determinant
+fn transform_normal(matrix: &Matrix, normal: [f32; 3]) -> [f32; 3] {
+    let a = [matrix[0][0], matrix[0][1], matrix[0][2]];
+    let b = [matrix[1][0], matrix[1][1], matrix[1][2]];
+    let c = [matrix[2][0], matrix[2][1], matrix[2][2]];
+    let bc = cross_product(b, c);
+    let ca = cross_product(c, a);
+    let ab = cross_product(a, b);
+    let sign = dot_product(a, bc).signum();
+    normalize([
+        (bc[0] * normal[0] + ca[0] * normal[1] + ab[0] * normal[2]) * sign,
+        (bc[1] * normal[0] + ca[1] * normal[1] + ab[1] * normal[2]) * sign,
+        (bc[2] * normal[0] + ca[2] * normal[1] + ab[2] * normal[2]) * sign,
+    ])
+}
+
+//gltf is right handed, the renderer looks down +z with y up, so z is mirrored
+fn to_scene(vector: [f32; 3]) -> [f32; 3] {
+    [vector[0], vector[1], -vector[2]]
+}
+
+fn node_matrix(node: &Value) -> Matrix {
+    if let Some(values) = node["matrix"].as_array() {
+        let mut matrix = IDENTITY;
+        for (i, value) in values.iter().enumerate().take(16) {
+            matrix[i / 4][i % 4] = value.as_f64().unwrap_or(0.0) as f32;
+        }
+        return matrix;
+    }
+    let vector = |value: &Value, default: [f32; 4]| -> [f32; 4] {
+        let mut result = default;
+        if let Some(values) = value.as_array() {
+            for (i, value) in values.iter().enumerate().take(4) {
+                result[i] = value.as_f64().unwrap_or(0.0) as f32;
+            }
+        }
+        result
+    };
+    let t = vector(&node["translation"], [0.0, 0.0, 0.0, 0.0]);
+    let [x, y, z, w] = vector(&node["rotation"], [0.0, 0.0, 0.0, 1.0]);
+    let s = vector(&node["scale"], [1.0, 1.0, 1.0, 0.0]);
+    //translation * rotation * scale
+    [
+        [(1.0 - 2.0 * (y * y + z * z)) * s[0], (2.0 * (x * y + z * w)) * s[0], (2.0 * (x * z - y * w)) * s[0], 0.0],
+        [(2.0 * (x * y - z * w)) * s[1], (1.0 - 2.0 * (x * x + z * z)) * s[1], (2.0 * (y * z + x * w)) * s[1], 0.0],
+        [(2.0 * (x * z + y * w)) * s[2], (2.0 * (y * z - x * w)) * s[2], (1.0 - 2.0 * (x * x + y * y)) * s[2], 0.0],
+        [t[0], t[1], t[2], 1.0],
+    ]
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for character in text.bytes() {
+        let value = match character {
+            b'A'..=b'Z' => character - b'A',
+            b'a'..=b'z' => character - b'a' + 26,
+            b'0'..=b'9' => character - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' | b'\n' | b'\r' | b' ' => continue,
+            _ => return Err(format!("invalid base64 character '{}'", character as char)),
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+//data uris are decoded in place, anything else is a file next to the gltf
+fn load_uri(directory: &Path, uri: &str) -> Result<Vec<u8>, String> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data.split_once(";base64,").ok_or("only base64 data uris are supported")?;
+        return decode_base64(encoded);
+    }
+    fs::read(directory.join(uri)).map_err(|error| format!("{}: {}", uri, error))
+}
+
+struct Document {
+    json: Value,
+    buffers: Vec<Vec<u8>>,
+    directory: String,
+}
+
+impl Document {
+    fn load(filename: &str) -> Result<Document, String> {
+        let bytes = fs::read(filename).map_err(|error| error.to_string())?;
+        let directory = Path::new(filename).parent().unwrap_or(Path::new(""));
+
+        //.glb is a 12 byte header followed by a json chunk and an optional binary chunk
+        let mut binary_chunk = None;
+        let json: Value = if bytes.starts_with(b"glTF") {
+            let read_u32 = |offset: usize| -> Result<u32, String> {
+                let slice = bytes.get(offset..offset + 4).ok_or("glb chunk runs past the end of the file")?;
+                Ok(u32::from_le_bytes(slice.try_into().unwrap()))
+            };
+            let mut offset = 12;
+            let mut json = None;
+            while offset + 8 <= bytes.len() {
+                let length = read_u32(offset)? as usize;
+                let kind = read_u32(offset + 4)?;
+                let chunk = bytes.get(offset + 8..offset + 8 + length).ok_or("glb chunk runs past the end of the file")?;
+                match kind {
+                    0x4E4F534A => json = Some(serde_json::from_slice(chunk).map_err(|error| error.to_string())?),
+                    0x004E4942 => binary_chunk = Some(chunk.to_vec()),
+                    _ => {}
+                }
+                offset += 8 + length;
+            }
+            json.ok_or("glb has no json chunk")?
+        } else {
+            serde_json::from_slice(&bytes).map_err(|error| error.to_string())?
+        };
+
+        let mut buffers = vec![];
+        for buffer in json["buffers"].as_array().unwrap_or(&vec![]) {
+            match buffer["uri"].as_str() {
+                Some(uri) => buffers.push(load_uri(directory, uri)?),
+                None => buffers.push(binary_chunk.take().ok_or("buffer has no uri and there is no glb binary chunk")?),
+            }
+        }
+        Ok(Document {
+            json,
+            buffers,
+            directory: directory.to_string_lossy().into_owned(),
+        })
+    }
+
+    fn buffer_view(&self, index: usize) -> Result<(&[u8], usize), String> {
+        let view = &self.json["bufferViews"][index];
+        let buffer = self.buffers.get(view["buffer"].as_u64().unwrap_or(0) as usize).ok_or("buffer view points at a missing buffer")?;
+        let offset = view["byteOffset"].as_u64().unwrap_or(0) as usize;
+        let length = view["byteLength"].as_u64().unwrap_or(0) as usize;
+        let data = buffer.get(offset..offset + length).ok_or("buffer view runs past the end of its buffer")?;
+        Ok((data, view["byteStride"].as_u64().unwrap_or(0) as usize))
+    }
+
+    //every element of an accessor as floats, integer data is normalised when the accessor asks for it
+    fn accessor(&self, index: usize) -> Result<Vec<Vec<f64>>, String> {
+        let accessor = &self.json["accessors"][index];
+        let count = accessor["count"].as_u64().ok_or("accessor has no count")? as usize;
+        let components = match accessor["type"].as_str() {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            Some("MAT4") => 16,
+            other => return Err(format!("unsupported accessor type {:?}", other)),
+        };
+        let component_type = accessor["componentType"].as_u64().unwrap_or(0);
+        let size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => return Err(format!("unsupported component type {}", component_type)),
+        };
+        let normalized = accessor["normalized"].as_bool().unwrap_or(false);
+        if !accessor["sparse"].is_null() {
+            return Err("sparse accessors are not supported".to_string());
+        }
+        //no buffer view means every element is zero
+        let Some(view) = accessor["bufferView"].as_u64() else {
+            return Ok(vec![vec![0.0; components]; count]);
+        };
+        let (data, stride) = self.buffer_view(view as usize)?;
+        let stride = if stride == 0 { size * components } else { stride };
+        let offset = accessor["byteOffset"].as_u64().unwrap_or(0) as usize;
+
+        let mut elements = Vec::with_capacity(count);
+        for i in 0..count {
+            let mut element = Vec::with_capacity(components);
+            for j in 0..components {
+                let start = offset + i * stride + j * size;
+                let bytes = data.get(start..start + size).ok_or("accessor runs past the end of its buffer view")?;
+                let value = match component_type {
+                    5120 => bytes[0] as i8 as f64,
+                    5121 => bytes[0] as f64,
+                    5122 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    5123 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    5125 => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+                    _ => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+                };
+                let value = match (normalized, component_type) {
+                    (true, 5120) => (value / 127.0).max(-1.0),
+                    (true, 5121) => value / 255.0,
+                    (true, 5122) => (value / 32767.0).max(-1.0),
+                    (true, 5123) => value / 65535.0,
+                    _ => value,
+                };
+                element.push(value);
+            }
+            elements.push(element);
+        }
+        Ok(elements)
+    }
+
+    //only png and jpeg images can be decoded, others are skipped with a warning
+    fn texture(&self, filename: &str, index: usize, color_space: ColorSpace, textures: &mut TextureCache) -> Result<Option<Arc<Texture>>, String> {
+        let texture = &self.json["textures"][index];
+        let Some(source) = texture["source"].as_u64() else {
+            return Ok(None);
+        };
+        let wrap = match self.json["samplers"][texture["sampler"].as_u64().unwrap_or(u64::MAX) as usize]["wrapS"].as_u64() {
+            Some(33071) => WrapMode::Clamp,
+            Some(33648) => WrapMode::Mirror,
+            _ => WrapMode::Repeat,
+        };
+        let image = &self.json["images"][source as usize];
+        let uri = image["uri"].as_str();
+        let mime_type = image["mimeType"].as_str().unwrap_or("");
+        let is_type = |mime: &str, extensions: &[&str]| mime_type == mime || uri.is_some_and(|uri| {
+            uri.starts_with(&format!("data:{}", mime)) || extensions.iter().any(|extension| uri.to_lowercase().ends_with(extension))
+        });
+        if !is_type("image/png", &[".png"]) && !is_type("image/jpeg", &[".jpg", ".jpeg"]) {
+            println!("Skipping image {} in {}, only png and jpeg textures are supported", source, filename);
+            return Ok(None);
+        }
+        let name = format!("{}#image{}", filename, source);
+        let bytes = match (uri, image["bufferView"].as_u64()) {
+            (Some(uri), _) => load_uri(Path::new(&self.directory), uri)?,
+            (None, Some(view)) => self.buffer_view(view as usize)?.0.to_vec(),
+            (None, None) => return Ok(None),
+        };
+        textures.load_bytes(&name, &bytes, wrap, color_space).map(Some)
+    }
+
+    fn material(&self, filename: &str, index: usize, textures: &mut TextureCache) -> Result<objects::Material, String> {
+        let material = &self.json["materials"][index];
+        let pbr = &material["pbrMetallicRoughness"];
+        let factor = |value: &Value, default: f64| value.as_f64().unwrap_or(default) as f32;
+
+        let base = &pbr["baseColorFactor"];
+        let mut result = objects::Material::new([factor(&base[0], 1.0), factor(&base[1], 1.0), factor(&base[2], 1.0)], 0.0, 0.0);
+        //the factors scale the metallic roughness texture when there is one
+        result.smoothness = 1.0 - factor(&pbr["roughnessFactor"], 1.0);
+        result.metallic = factor(&pbr["metallicFactor"], 1.0);
+        if material["alphaMode"].as_str() == Some("BLEND") {
+            result.opacity = factor(&base[3], 1.0);
+        }
+        let extensions = &material["extensions"];
+        if let Some(transmission) = extensions["KHR_materials_transmission"]["transmissionFactor"].as_f64() {
+            result.opacity = 1.0 - transmission as f32;
+        }
+        result.ior = factor(&extensions["KHR_materials_ior"]["ior"], 1.5);
+
+        let emissive = &material["emissiveFactor"];
+        let emission = [factor(&emissive[0], 0.0), factor(&emissive[1], 0.0), factor(&emissive[2], 0.0)];
+        if emission.iter().any(|&channel| channel > 0.0) {
+            result.emission_color = Some(emission);
+            result.light = factor(&extensions["KHR_materials_emissive_strength"]["emissiveStrength"], 1.0);
+        }
+
+        if let Some(texture) = pbr["baseColorTexture"]["index"].as_u64() {
+            result.albedo_texture = self.texture(filename, texture as usize, ColorSpace::Srgb, textures)?;
+        }
+        if let Some(texture) = pbr["metallicRoughnessTexture"]["index"].as_u64() {
+            result.roughness_texture = self.texture(filename, texture as usize, ColorSpace::Linear, textures)?;
+            result.roughness_channel = 1;
+            result.metallic_texture = result.roughness_texture.clone();
+            result.metallic_channel = 2;
+        }
+        if let Some(texture) = material["emissiveTexture"]["index"].as_u64() {
+            result.emission_texture = self.texture(filename, texture as usize, ColorSpace::Srgb, textures)?;
+            result.light = result.light.max(1.0);
+        }
+        Ok(result)
+    }
+}
+
+//read a .gltf or .glb scene with its node transforms, the materials can be overridden by the scene entry
+pub fn extract_scene<F: Fn(&mut objects::Material)>(filename: &str, placement: &Placement, textures: &mut TextureCache, override_material: F) -> Result<GltfScene, MeshError> {
+    let error = |message: String| MeshError::new(filename, 0, message);
+    let document = Document::load(filename).map_err(error)?;
+    let json = &document.json;
+
+    let mut default_material = objects::Material::new([1.0, 1.0, 1.0], 0.0, 0.0);
+    override_material(&mut default_material);
+    let mut materials = vec![];
+    for index in 0..json["materials"].as_array().map_or(0, |materials| materials.len()) {
+        let mut material = document.material(filename, index, textures).map_err(error)?;
+        override_material(&mut material);
+        materials.push(material);
+    }
+
+    //nodes listed by the default scene, or every node that isn't somebody's child
+    let roots: Vec<usize> = match json["scenes"][json["scene"].as_u64().unwrap_or(0) as usize]["nodes"].as_array() {
+        Some(nodes) => nodes.iter().filter_map(|node| node.as_u64()).map(|node| node as usize).collect(),
+        None => {
+            let nodes = json["nodes"].as_array().map_or(0, |nodes| nodes.len());
+            let children: Vec<u64> = json["nodes"].as_array().unwrap_or(&vec![]).iter()
+                .flat_map(|node| node["children"].as_array().cloned().unwrap_or_default())
+                .filter_map(|child| child.as_u64())
+                .collect();
+            (0..nodes).filter(|node| !children.contains(&(*node as u64))).collect()
+        }
+    };
+
+    let mut triangles = vec![];
+    let mut lights = vec![];
+    let mut camera = None;
+    let mut stack: Vec<(usize, Matrix)> = roots.into_iter().map(|node| (node, IDENTITY)).collect();
+    while let Some((index, parent)) = stack.pop() {
+        let node = &json["nodes"][index];
+        let matrix = multiply(&parent, &node_matrix(node));
+        for child in node["children"].as_array().unwrap_or(&vec![]) {
+            if let Some(child) = child.as_u64() {
+                stack.push((child as usize, matrix));
+            }
+        }
+
+        if let Some(mesh) = node["mesh"].as_u64() {
+            for primitive in json["meshes"][mesh as usize]["primitives"].as_array().unwrap_or(&vec![]) {
+                let material = match primitive["material"].as_u64() {
+                    Some(material) => materials.get(material as usize).unwrap_or(&default_material),
+                    None => &default_material,
+                };
+                let primitive_triangles = extract_primitive(&document, primitive, &matrix, material)
+                    .map_err(|message| error(format!("mesh {}: {}", mesh, message)))?;
+                triangles.extend(primitive_triangles);
+            }
+        }
+
+        if camera.is_none() {
+            if let Some(index) = node["camera"].as_u64() {
+                let definition = &json["cameras"][index as usize];
+                if definition["type"].as_str() == Some("perspective") {
+                    let perspective = &definition["perspective"];
+                    let yfov = perspective["yfov"].as_f64().unwrap_or(0.8) as f32;
+                    let aspect = perspective["aspectRatio"].as_f64().unwrap_or(16.0 / 9.0) as f32;
+                    let mut node_camera = objects::Camera::new(to_scene(transform_point(&matrix, [0.0, 0.0, 0.0])));
+                    node_camera.right = normalize(to_scene(transform_direction(&matrix, [1.0, 0.0, 0.0])));
+                    node_camera.up = normalize(to_scene(transform_direction(&matrix, [0.0, 1.0, 0.0])));
+                    node_camera.forward = normalize(to_scene(transform_direction(&matrix, [0.0, 0.0, -1.0])));
+                    //the renderer's field of view is horizontal
+                    node_camera.fov = Some(yfov * aspect);
+                    camera = Some(node_camera);
+                }
+            }
+        }
+
+        if let Some(index) = node["extensions"]["KHR_lights_punctual"]["light"].as_u64() {
+            let light = &json["extensions"]["KHR_lights_punctual"]["lights"][index as usize];
+            let color = &light["color"];
+            let color = [
+                color[0].as_f64().unwrap_or(1.0) as f32,
+                color[1].as_f64().unwrap_or(1.0) as f32,
+                color[2].as_f64().unwrap_or(1.0) as f32,
+            ];
+            //candela for point and spot lights, which is the renderer's intensity, or lux for directional ones
+            let intensity = light["intensity"].as_f64().unwrap_or(1.0) as f32;
+            let position = to_scene(transform_point(&matrix, [0.0, 0.0, 0.0]));
+            //lights shine down their node's -z
+            let direction = to_scene(transform_direction(&matrix, [0.0, 0.0, -1.0]));
+            match light["type"].as_str() {
+                Some("point") => lights.push(Light::point(position, 4.0 * PI * intensity, color)),
+                Some("spot") => {
+                    let inner = light["spot"]["innerConeAngle"].as_f64().unwrap_or(0.0) as f32;
+                    let outer = light["spot"]["outerConeAngle"].as_f64().unwrap_or(PI as f64 / 4.0) as f32;
+                    lights.push(Light::spot(position, direction, 4.0 * PI * intensity, color, inner.to_degrees(), outer.to_degrees()));
+                }
+                Some("directional") => lights.push(Light::directional(direction, intensity, color)),
+                other => println!("Skipping {:?} light {} in {}, it isn't a gltf light type", other, index, filename),
+            }
+        }
+    }
+
+    let place = placement.mapping(&triangles);
+    for light in &mut lights {
+        match light {
+            Light::Point { position, .. } => *position = place(*position),
+            Light::Spot { position, direction, .. } => {
+                *position = place(*position);
+                *direction = placement.rotate(*direction);
+            }
+            Light::Directional { direction, .. } => *direction = placement.rotate(*direction),
+            Light::Area { .. } => {}
+        }
+    }
+    if let Some(camera) = &mut camera {
+        camera.position = place(camera.position);
+        camera.right = placement.rotate(camera.right);
+        camera.up = placement.rotate(camera.up);
+        camera.forward = placement.rotate(camera.forward);
+    }
+    Ok(GltfScene {
+        triangles: placement.apply(triangles),
+        lights,
+        camera,
+    })
+}
+
+fn extract_primitive(document: &Document, primitive: &Value, matrix: &Matrix, material: &objects::Material) -> Result<Vec<objects::Triangle>, String> {
+    let attributes = &primitive["attributes"];
+    let positions = document.accessor(attributes["POSITION"].as_u64().ok_or("primitive has no positions")? as usize)?;
+    let normals = match attributes["NORMAL"].as_u64() {
+        Some(accessor) => Some(document.accessor(accessor as usize)?),
+        None => None,
+    };
+    let uvs = match attributes["TEXCOORD_0"].as_u64() {
+        Some(accessor) => Some(document.accessor(accessor as usize)?),
+        None => None,
+    };
+    let colors = match attributes["COLOR_0"].as_u64() {
+        Some(accessor) => Some(document.accessor(accessor as usize)?),
+        None => None,
+    };
+    let indices: Vec<usize> = match primitive["indices"].as_u64() {
+        Some(accessor) => document.accessor(accessor as usize)?.iter().map(|index| index[0] as usize).collect(),
+        None => (0..positions.len()).collect(),
+    };
+    if let Some(&outside) = indices.iter().find(|&&index| index >= positions.len()) {
+        return Err(format!("index {} out of range, {} defined", outside, positions.len()));
+    }
+
+    let corners: Vec<[usize; 3]> = match primitive["mode"].as_u64().unwrap_or(4) {
+        4 => indices.chunks_exact(3).map(|chunk| [chunk[0], chunk[1], chunk[2]]).collect(),
+        5 => (2..indices.len()).map(|i| if i % 2 == 0 {
+            [indices[i - 2], indices[i - 1], indices[i]]
+        } else {
+            [indices[i - 1], indices[i - 2], indices[i]]
+        }).collect(),
+        6 => (2..indices.len()).map(|i| [indices[0], indices[i - 1], indices[i]]).collect(),
+        mode => {
+            println!("Skipping primitive with mode {}, only triangles are rendered", mode);
+            vec![]
+        }
+    };
+
+    let vector = |element: &Vec<f64>| [element[0] as f32, element[1] as f32, element[2] as f32];
+    let mut triangles = Vec::with_capacity(corners.len());
+    for corner in corners {
+        //mirroring z turns the winding around, so two corners swap to keep the faces pointing out
+        let corner = [corner[0], corner[2], corner[1]];
+        let vertices = corner.map(|index| to_scene(transform_point(matrix, vector(&positions[index]))));
+        //gltf puts v = 0 at the top of the image
+        let face_uvs = match &uvs {
+            Some(uvs) => corner.map(|index| [uvs[index][0] as f32, 1.0 - uvs[index][1] as f32]),
+            None => [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]],
+        };
+        let mut triangle = objects::Triangle::new(vertices, face_uvs, material.clone());
+        if let Some(normals) = &normals {
+            triangle.vertex_normals = Some(corner.map(|index| to_scene(transform_normal(matrix, vector(&normals[index])))));
+        }
+        if let Some(colors) = &colors {
+            triangle.vertex_colors = Some(corner.map(|index| vector(&colors[index])));
+        }
+        triangles.push(triangle);
+    }
+    Ok(triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::objects::{Hittable, Ray};
+
+    //a unit cube around the origin with every face wound counter clockwise seen from outside, as gltf wants
+    fn cube_gltf(name: &str) -> String {
+        let mut positions: Vec<u8> = vec![];
+        for axis in 0..3 {
+            for sign in [1.0f32, -1.0] {
+                let corner = |du: f32, dv: f32| {
+                    let mut point = [0.0f32; 3];
+                    point[axis] = sign * 0.5;
+                    point[(axis + 1) % 3] = du * 0.5;
+                    point[(axis + 2) % 3] = dv * 0.5;
+                    point
+                };
+                let mut quad = [corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0), corner(-1.0, 1.0)];
+                if sign < 0.0 {
+                    quad.reverse();
+                }
+                for index in [0, 1, 2, 0, 2, 3] {
+                    positions.extend(quad[index].iter().flat_map(|value| value.to_le_bytes()));
+                }
+            }
+        }
+        let directory = std::env::temp_dir();
+        let stem = format!("gltfmanager_{}_{}", name, std::process::id());
+        fs::write(directory.join(format!("{}.bin", stem)), &positions).unwrap();
+        let json = format!(r#"{{
+            "asset": {{"version": "2.0"}},
+            "scenes": [{{"nodes": [0]}}],
+            "nodes": [{{"mesh": 0}}],
+            "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}}}]}}],
+            "accessors": [{{"bufferView": 0, "componentType": 5126, "count": 36, "type": "VEC3"}}],
+            "bufferViews": [{{"buffer": 0, "byteLength": {length}}}],
+            "buffers": [{{"uri": "{stem}.bin", "byteLength": {length}}}]
+        }}"#, length = positions.len(), stem = stem);
+        let path = directory.join(format!("{}.gltf", stem));
+        fs::write(&path, json).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn faces_point_out_after_mirroring() {
+        let filename = cube_gltf("winding");
+        let scene = extract_scene(&filename, &Placement::new([0.0; 3], [1.0; 3]), &mut TextureCache::new(), |_| {});
+        fs::remove_file(&filename).unwrap();
+        fs::remove_file(filename.replace(".gltf", ".bin")).unwrap();
+        let triangles = scene.unwrap().triangles;
+        assert_eq!(triangles.len(), 12);
+        for axis in 0..3 {
+            for sign in [1.0, -1.0] {
+                //aimed a little off the middle of the face so it doesn't land on the diagonal
+                let mut origin = [0.1, 0.2, 0.3];
+                origin[axis] = sign * 3.0;
+                let mut direction = [0.0; 3];
+                direction[axis] = -sign;
+                let ray = Ray::new(origin, direction);
+                let hit = triangles.iter()
+                    .filter_map(|triangle| triangle.intersect(&ray, 0.0, f32::INFINITY))
+                    .min_by(|a, b| a.t.total_cmp(&b.t))
+                    .unwrap();
+                assert!(hit.front_face, "ray along axis {} from {} hit the inside", axis, sign);
+                assert!((hit.t - 2.5).abs() < 1e-4);
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+use rand::prelude::*;
+use super::objects::{Hittable, Ray, dot_product, normalize, subtract};
+
+//lights that aren't part of the scene's geometry, power is in watts with distances in scene units as metres,
+//and a radiance of 1 comes out as full white
+#[derive(Clone)]
+pub enum Light {
+    //intensity is per steradian, the same in every direction
+    Point { position: [f32; 3], intensity: [f32; 3] },
+    //full intensity inside the inner cone fading out to nothing at the outer one
+    Spot { position: [f32; 3], direction: [f32; 3], intensity: [f32; 3], cos_inner: f32, cos_outer: f32 },
+    //parallel light travelling along direction, irradiance is per square metre facing it
+    Directional { direction: [f32; 3], irradiance: [f32; 3] },
+    //flat emitter that glows from the side its normal faces, shape is a quad or disk of the given area
+    Area { shape: Arc<dyn Hittable>, area: f32, radiance: [f32; 3] },
+}
+
+//light reaching a point from one light, pdf is per unit solid angle and None for lights that are a single direction
+pub struct Illumination {
+    pub direction: [f32; 3],
+    pub distance: f32,
+    pub radiance: [f32; 3],
+    pub pdf: Option<f32>,
+}
+
+impl Light {
+    pub fn point(position: [f32; 3], power: f32, color: [f32; 3]) -> Light {
+        Light::Point {
+            position,
+            intensity: color.map(|value| value * power / (4.0 * PI)),
+        }
+    }
+    //angles are measured from the axis in degrees, the power is what it would give off with no cone at all
+    pub fn spot(position: [f32; 3], direction: [f32; 3], power: f32, color: [f32; 3], inner_angle: f32, outer_angle: f32) -> Light {
+        Light::Spot {
+            position,
+            direction: normalize(direction),
+            intensity: color.map(|value| value * power / (4.0 * PI)),
+            cos_inner: inner_angle.min(outer_angle).to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos(),
+        }
+    }
+    pub fn directional(direction: [f32; 3], irradiance: f32, color: [f32; 3]) -> Light {
+        Light::Directional {
+            direction: normalize(direction),
+            irradiance: color.map(|value| value * irradiance),
+        }
+    }
+    pub fn area(shape: Arc<dyn Hittable>, area: f32, power: f32, color: [f32; 3]) -> Light {
+        Light::Area {
+            shape,
+            area,
+            //a one sided diffuse emitter sends out pi * area times its radiance
+            radiance: color.map(|value| value * power / (PI * area)),
+        }
+    }
+
+    //point, spot and directional lights can't be hit by a bounce, only found by aiming shadow rays at them
+    pub fn is_delta(&self) -> bool {
+        !matches!(self, Light::Area { .. })
+    }
+
+    pub fn sample(&self, point: [f32; 3], rng: &mut ThreadRng) -> Option<Illumination> {
+        match self {
+            Light::Point { position, intensity } => Some(towards(point, *position, *intensity)),
+            Light::Spot { position, direction, intensity, cos_inner, cos_outer } => {
+                let mut illumination = towards(point, *position, *intensity);
+                let cos = -dot_product(illumination.direction, *direction);
+                let falloff = smoothstep(*cos_outer, *cos_inner, cos);
+                if falloff <= 0.0 {
+                    return None;
+                }
+                illumination.radiance = illumination.radiance.map(|value| value * falloff);
+                Some(illumination)
+            }
+            Light::Directional { direction, irradiance } => Some(Illumination {
+                direction: direction.map(|value| -value),
+                distance: f32::INFINITY,
+                radiance: *irradiance,
+                pdf: None,
+            }),
+            Light::Area { shape, radiance, .. } => {
+                let sample = shape.sample_surface(rng)?;
+                let offset = subtract(sample.point, point);
+                let distance = dot_product(offset, offset).sqrt();
+                let direction = offset.map(|value| value / distance);
+                let cos = -dot_product(sample.normal, direction);
+                if cos <= 0.0 {
+                    return None;
+                }
+                Some(Illumination {
+                    direction,
+                    distance,
+                    radiance: *radiance,
+                    pdf: Some(sample.pdf * distance * distance / cos),
+                })
+            }
+        }
+    }
+
+    //only area lights take up space, gives the distance along the ray, the light leaving the front and the pdf sample would have had
+    pub fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, [f32; 3], f32)> {
+        let Light::Area { shape, area, radiance } = self else {
+            return None;
+        };
+        let hit = shape.intersect(ray, t_min, t_max)?;
+        let length = dot_product(ray.direction, ray.direction).sqrt();
+        let distance = hit.t * length;
+        let cos = -dot_product(hit.normal, ray.direction) / length;
+        let emitted = if hit.front_face { *radiance } else { [0.0; 3] };
+        Some((hit.t, emitted, distance * distance / (cos.max(1e-6) * area)))
+    }
+}
+
+fn towards(point: [f32; 3], position: [f32; 3], intensity: [f32; 3]) -> Illumination {
+    let offset = subtract(position, point);
+    let distance_squared = dot_product(offset, offset);
+    let distance = distance_squared.sqrt();
+    Illumination {
+        direction: offset.map(|value| value / distance),
+        distance,
+        radiance: intensity.map(|value| value / distance_squared),
+        pdf: None,
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    if edge1 <= edge0 {
+        return if x >= edge0 { 1.0 } else { 0.0 };
+    }
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+//linear srgb of a blackbody at kelvin, scaled to a luminance of 1 so it only tints the light
+pub fn temperature_color(kelvin: f32) -> [f32; 3] {
+    let mut xyz = [0.0; 3];
+    for step in 0..=80 {
+        let wavelength = 380.0 + step as f32 * 5.0;
+        //planck's law with the constant factors dropped, they cancel when normalising
+        let metres = wavelength * 1e-9;
+        let spectral = 1.0 / (metres.powi(5) * ((1.4388e-2 / (metres * kelvin)).exp() - 1.0));
+        let matching = color_matching(wavelength);
+        for i in 0..3 {
+            xyz[i] += spectral * matching[i];
+        }
+    }
+    let rgb = xyz_to_linear_srgb(xyz.map(|value| value / xyz[1]));
+    rgb.map(|value| value.max(0.0))
+}
+
+//wyman, sloan and shirley's fit of the cie 1931 observer
+fn color_matching(wavelength: f32) -> [f32; 3] {
+    let lobe = |mean: f32, below: f32, above: f32| {
+        let spread = if wavelength < mean { below } else { above };
+        (-0.5 * ((wavelength - mean) / spread).powi(2)).exp()
+    };
+    [
+        1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7) - 0.065 * lobe(501.1, 20.4, 26.2),
+        0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8),
+    ]
+}
+
+pub fn xyz_to_linear_srgb(xyz: [f32; 3]) -> [f32; 3] {
+    [
+        3.2406 * xyz[0] - 1.5372 * xyz[1] - 0.4986 * xyz[2],
+        -0.9689 * xyz[0] + 1.8758 * xyz[1] + 0.0415 * xyz[2],
+        0.0557 * xyz[0] - 0.2040 * xyz[1] + 1.0570 * xyz[2],
+    ]
+}
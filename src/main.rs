@@ -1,15 +1,34 @@
 
 use core::f32;
-use std::thread::{self, Thread};
+use std::thread;
 use std::sync::mpsc;
 use std::io::Write;
-use png;
 use std::time;
 
 mod scene_manager;
 mod png_manager;
 mod objects;
 mod objmanager;
+mod gltfmanager;
+mod plymanager;
+mod stlmanager;
+mod texture_manager;
+mod primitives;
+mod bvh;
+mod csg;
+mod sdf;
+mod environment;
+mod lights;
+mod volumes;
+mod voxelmanager;
+mod framebuffer;
+mod exr_manager;
+mod denoiser;
+mod filters;
+mod animation;
+mod motion;
+mod distributed;
+mod preview;
 
 const LOGGING: bool = false;
 
@@ -17,8 +36,16 @@ const WIDTH: usize = 1920;
 const HEIGHT: usize = 1080;
 const FOV: f32 = 90.0 * f32::consts::PI / 180.0; // 100 degrees
 const SAMPLES_PER_PIXEL: usize = 4096;
-const MAX_BOUNCES: usize = 5;
+//paths can stop at random after this many bounces, and never go past the limit for each kind
+const MIN_BOUNCES: usize = 3;
+const MAX_DIFFUSE_BOUNCES: usize = 8;
+const MAX_SPECULAR_BOUNCES: usize = 16;
+const MAX_TRANSMISSION_BOUNCES: usize = 16;
 const ANTI_ALIASING: bool = true;
+//reconstruction filter samples are spread out with, box keeps each sample to its own pixel
+const FILTER: &str = "box";
+//fraction of a frame the shutter stays open for, anything animated blurs along where it goes in that time
+const SHUTTER: f32 = 0.5;
 const SCENE_FILE: &str = "scenes/zach2.json";
 
 const THREAD_COUNT: usize = 18;
@@ -28,16 +55,135 @@ fn status_print(saved_chunks: usize, total_chunks: usize, elapsed_time: u64) {
     print!("Saved chunks: {}/{} Time Elapsed: {}s/{}s\r", saved_chunks, total_chunks, elapsed_time, elapsed_time * total_chunks as u64 / saved_chunks as u64);
     std::io::stdout().flush().unwrap();
 }
+//value given after a flag, like mitchell in --filter mitchell
+fn argument_value(name: &str) -> Option<String> {
+    let arguments: Vec<String> = std::env::args().collect();
+    let index = arguments.iter().position(|argument| argument == name)?;
+    arguments.get(index + 1).cloned()
+}
+//adds a finished chunk to the film, then updates every pixel it reached in both the png and the float framebuffer
+fn store_chunk(image: &mut png_manager::Image, framebuffer: &mut framebuffer::Framebuffer, film: &mut framebuffer::Tile, tile: &framebuffer::Tile, preview: Option<&preview::Preview>) {
+    film.merge(tile);
+    for y in tile.y.max(0)..(tile.y + tile.height as i64).min(HEIGHT as i64) {
+        for x in tile.x.max(0)..(tile.x + tile.width as i64).min(WIDTH as i64) {
+            let pixel = film.resolve(x as usize, y as usize);
+            image.set_pixel(x as u32, y as u32, pixel.to_rgba());
+            framebuffer.set_pixel(x as usize, y as usize, pixel);
+        }
+    }
+    if let Some(preview) = preview {
+        preview.update(image, tile);
+    }
+}
+//frames from --frames, either one frame or an inclusive range like 0-47
+fn parse_frames(frames: &str) -> (usize, usize) {
+    let parse = |frame: &str| frame.trim().parse::<usize>().unwrap_or_else(|_| panic!("Invalid frame {}", frame));
+    match frames.split_once('-') {
+        Some((start, end)) => (parse(start), parse(end)),
+        None => (parse(frames), parse(frames)),
+    }
+}
 fn main() {
     //take in --scene argument
     //let scene_file = std::env::args()
+    //--denoise also saves a filtered copy of the image, the raw one is kept
+    let denoise = std::env::args().any(|argument| argument == "--denoise");
+    //--filter and --filter-radius pick the reconstruction filter
+    let filter_name = argument_value("--filter").unwrap_or(FILTER.to_string());
+    let filter_radius = argument_value("--filter-radius").map(|radius| radius.parse().expect("Filter radius must be a number"));
+    let filter = filters::Filter::new(&filter_name, filter_radius).unwrap_or_else(|error| {
+        println!("{}", error);
+        std::process::exit(1);
+    });
+    //--frames renders an image sequence of the animation instead of a single image of its first frame
+    let frames = argument_value("--frames").map(|frames| parse_frames(&frames));
+    //--shutter 0 turns motion blur off
+    let shutter = argument_value("--shutter").map_or(SHUTTER, |shutter| shutter.parse().expect("Shutter must be a number"));
+    //--aovs also saves every pass as floats in an exr next to the png
+    let write_aovs = std::env::args().any(|argument| argument == "--aovs");
 
-    if WIDTH % THREAD_CHUNK_SIZE != 0 || HEIGHT % THREAD_CHUNK_SIZE != 0 {
+    if !WIDTH.is_multiple_of(THREAD_CHUNK_SIZE) || !HEIGHT.is_multiple_of(THREAD_CHUNK_SIZE) {
         panic!("Width and height must be divisible by thread chunk size");
     }
 
+    let settings = scene_manager::PathSettings::new(MIN_BOUNCES, MAX_DIFFUSE_BOUNCES, MAX_SPECULAR_BOUNCES, MAX_TRANSMISSION_BOUNCES);
+
+    //--worker renders tiles for a coordinator at that address instead of an image of its own
+    if let Some(address) = argument_value("--worker") {
+        distributed::work(&address, THREAD_COUNT);
+        return;
+    }
+    //--coordinator listens on that address and has workers render every tile
+    let coordinator = argument_value("--coordinator").map(|address| distributed::Coordinator::start(&address, distributed::Job {
+        scene: SCENE_FILE.to_string(),
+        width: WIDTH,
+        height: HEIGHT,
+        chunk: THREAD_CHUNK_SIZE,
+        samples: SAMPLES_PER_PIXEL,
+        antialiasing: ANTI_ALIASING,
+        fov: FOV,
+        settings,
+        filter,
+        shutter,
+    }));
+
+    //--preview serves the image as it renders over http on that address
+    let preview = argument_value("--preview").map(|address| preview::Preview::start(&address, WIDTH, HEIGHT, SAMPLES_PER_PIXEL));
+
+    let data = scene_manager::read_scene_file(SCENE_FILE);
+    //meshes that don't move are only loaded and built once for the whole sequence
+    let mut cache = scene_manager::SceneCache::new();
+    let start_time = time::Instant::now();
+    let (first, last) = frames.unwrap_or((0, 0));
+    for frame in first..=last {
+        if let Some(preview) = &preview {
+            preview.begin_frame(frame, WIDTH / THREAD_CHUNK_SIZE * HEIGHT / THREAD_CHUNK_SIZE);
+        }
+        let (mut image, framebuffer) = match &coordinator {
+            Some(coordinator) => coordinator.render(frame, preview.as_deref()),
+            None => {
+                let scene = scene_manager::Scene::at_frame(&data, frame as f32, shutter, &mut cache).unwrap_or_else(|error| {
+                    println!("{}", error);
+                    std::process::exit(1);
+                });
+                render(&scene, settings, filter, preview.as_deref())
+            }
+        };
+        let filename = match frames {
+            Some(_) => format!("images/frame_{:04}.png", frame),
+            None => png_manager::create_unused_filename(),
+        };
+        image.update_filename(filename);
+        image.save_image();
+        if write_aovs {
+            let filename = image.filename.replace(".png", ".exr");
+            exr_manager::save(&framebuffer, &filename).expect("Could not write the exr");
+        }
+        if denoise {
+            let denoised = denoiser::denoise(&framebuffer);
+            let mut denoised_image = png_manager::Image::new(WIDTH as u32, HEIGHT as u32);
+            for y in 0..HEIGHT {
+                for x in 0..WIDTH {
+                    denoised_image.set_pixel(x as u32, y as u32, denoised.pixels[y * WIDTH + x].to_rgba());
+                }
+            }
+            denoised_image.update_filename(image.filename.replace(".png", "_denoised.png"));
+            denoised_image.save_image();
+        }
+        if frames.is_some() {
+            println!("\nFrame {} done", frame);
+        }
+    }
+    if let Some(coordinator) = &coordinator {
+        coordinator.finish();
+    }
+    println!("\nTotal time elapsed: {}s", start_time.elapsed().as_secs());
+}
+//renders the whole image across the worker threads
+fn render(scene: &scene_manager::Scene, settings: scene_manager::PathSettings, filter: filters::Filter, preview: Option<&preview::Preview>) -> (png_manager::Image, framebuffer::Framebuffer) {
     let mut image = png_manager::Image::new(WIDTH as u32, HEIGHT as u32);
-    let scene = scene_manager::Scene::new(SCENE_FILE.to_string());
+    let mut framebuffer = framebuffer::Framebuffer::new(WIDTH, HEIGHT);
+    let mut film = framebuffer::Tile::new(0, 0, WIDTH, HEIGHT, filter);
 
     let mut threads = vec![];
 
@@ -51,12 +197,7 @@ fn main() {
         communications_senders.push(ttx);
         let cloned_scene = scene.clone();
         threads.push(thread::spawn(move || {
-            let mut rng = rand::thread_rng();
-            loop {
-                let (x, y) = match rrx.recv() {
-                    Ok((x, y)) => (x, y),
-                    Err(_) => break,
-                };
+            while let Ok((x, y)) = rrx.recv() {
                 if x == -1 && y == -1 {
                     break;
                 }
@@ -65,14 +206,13 @@ fn main() {
 
                 let init_x = x * THREAD_CHUNK_SIZE;
                 let init_y = y * THREAD_CHUNK_SIZE;
-                let mut colors: [[u8; 3]; THREAD_CHUNK_SIZE * THREAD_CHUNK_SIZE]   = [[0, 0, 0]; THREAD_CHUNK_SIZE * THREAD_CHUNK_SIZE];
+                let mut tile = framebuffer::Tile::new(init_x, init_y, THREAD_CHUNK_SIZE, THREAD_CHUNK_SIZE, filter);
                 for y in init_y..init_y+THREAD_CHUNK_SIZE {
                     for x in init_x..init_x+THREAD_CHUNK_SIZE {
-                        let color = cloned_scene.trace(x, y, MAX_BOUNCES, SAMPLES_PER_PIXEL, ANTI_ALIASING, WIDTH, HEIGHT, FOV);
-                        colors[(y - init_y) * THREAD_CHUNK_SIZE + (x - init_x)] = color;
+                        cloned_scene.trace(x, y, &settings, SAMPLES_PER_PIXEL, ANTI_ALIASING, WIDTH, HEIGHT, FOV, &mut tile);
                     }
                 }
-                tx.send((i, x, y, colors)).unwrap();
+                tx.send((i, tile)).unwrap();
             }
         }));
     }
@@ -80,7 +220,7 @@ fn main() {
     let mut working_threads = 0;
 
     let start_time = time::Instant::now();
-    for mut y in 0..HEIGHT/THREAD_CHUNK_SIZE {
+    for y in 0..HEIGHT/THREAD_CHUNK_SIZE {
         for x in 0..WIDTH/THREAD_CHUNK_SIZE {
             //very interesting render pattern when uncommented
             //y = HEIGHT/THREAD_CHUNK_SIZE - y - 1;
@@ -90,13 +230,8 @@ fn main() {
                 working_threads += 1;
                 continue;
             }
-            let (thread_wanted, x_arrived, y_arrived, colors) = rx.recv().unwrap();
-            for offset_y in 0..THREAD_CHUNK_SIZE {
-                for offset_x in 0..THREAD_CHUNK_SIZE {
-                    let color = colors[offset_y * THREAD_CHUNK_SIZE + offset_x];
-                    image.set_pixel((x_arrived * THREAD_CHUNK_SIZE + offset_x) as u32, (y_arrived * THREAD_CHUNK_SIZE + offset_y) as u32, [color[0], color[1], color[2], 255]);
-                }
-            }
+            let (thread_wanted, tile) = rx.recv().unwrap();
+            store_chunk(&mut image, &mut framebuffer, &mut film, &tile, preview);
             communications_senders[thread_wanted].send((x as i32, y as i32)).unwrap();
 
             if LOGGING {
@@ -110,13 +245,8 @@ fn main() {
     }
     let mut awaiting = threads.len();
     while awaiting > 0 {
-        let (thread_wanted, x_arrived, y_arrived, colors) = rx.recv().unwrap();
-        for offset_y in 0..THREAD_CHUNK_SIZE {
-            for offset_x in 0..THREAD_CHUNK_SIZE {
-                let color = colors[offset_y * THREAD_CHUNK_SIZE + offset_x];
-                image.set_pixel((x_arrived * THREAD_CHUNK_SIZE + offset_x) as u32, (y_arrived * THREAD_CHUNK_SIZE + offset_y) as u32, [color[0], color[1], color[2], 255]);
-            }
-        }
+        let (thread_wanted, tile) = rx.recv().unwrap();
+        store_chunk(&mut image, &mut framebuffer, &mut film, &tile, preview);
         communications_senders[thread_wanted].send((-1, -1)).unwrap();
 
         if LOGGING {
@@ -145,8 +275,5 @@ fn main() {
         }
     }
     */
-    image.update_filename(png_manager::create_unused_filename());
-    image.save_image();
-    println!("\nTotal time elapsed: {}s", start_time.elapsed().as_secs());
+    (image, framebuffer)
 }
-
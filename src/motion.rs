@@ -0,0 +1,88 @@
+use std::sync::Arc;
+use rand::prelude::*;
+use super::bvh::Aabb;
+use super::objects::{Hit, Hittable, Ray, SurfaceSample, dot_product, subtract};
+use super::primitives::Transform;
+
+//an object that moves rigidly while the shutter is open. it is built where it is at shutter open, and each ray is
+//moved into that object's space for wherever the object has got to by the ray's time, placements are a position and
+//rotation in degrees the same as shapes use, the object turning about the position
+pub struct Moving {
+    pub object: Arc<dyn Hittable>,
+    pub open: ([f32; 3], [f32; 3]),
+    pub close: ([f32; 3], [f32; 3]),
+    //box the object covers over the whole shutter, so the bvh can hold it like any other object
+    bounds: Option<Aabb>,
+}
+
+impl Moving {
+    pub fn new(object: Arc<dyn Hittable>, open: ([f32; 3], [f32; 3]), close: ([f32; 3], [f32; 3])) -> Moving {
+        let mut moving = Moving {
+            object,
+            open,
+            close,
+            bounds: None,
+        };
+        moving.bounds = moving.object.bounds().map(|bounds| {
+            let mut corners = vec![];
+            for x in [bounds.min[0], bounds.max[0]] {
+                for y in [bounds.min[1], bounds.max[1]] {
+                    for z in [bounds.min[2], bounds.max[2]] {
+                        corners.push([x, y, z]);
+                    }
+                }
+            }
+            //sliding without turning sweeps the box straight from one end to the other
+            if moving.open.1 == moving.close.1 {
+                let start = moving.placement(0.0);
+                let end = moving.placement(1.0);
+                let moved: Vec<[f32; 3]> = corners.iter().map(|&corner| end.point_to_world(start.point_to_local(corner))).collect();
+                return Aabb::from_points(&corners).union(&Aabb::from_points(&moved));
+            }
+            //while turning every point stays within the furthest corner's distance of the pivot as it slides
+            let radius = corners.iter()
+                .map(|&corner| subtract(corner, moving.open.0))
+                .fold(0.0_f32, |largest, offset| largest.max(dot_product(offset, offset).sqrt()));
+            let reach = |pivot: [f32; 3]| Aabb::new(pivot.map(|value| value - radius), pivot.map(|value| value + radius));
+            reach(moving.open.0).union(&reach(moving.close.0))
+        });
+        moving
+    }
+
+    fn placement(&self, time: f32) -> Transform {
+        let lerp = |a: [f32; 3], b: [f32; 3]| [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * time);
+        Transform::new(lerp(self.open.0, self.close.0), lerp(self.open.1, self.close.1))
+    }
+}
+
+impl Hittable for Moving {
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+        let start = self.placement(0.0);
+        let now = self.placement(ray.time);
+        let (origin, direction) = now.local_ray(ray);
+        let moved = Ray {
+            origin: start.point_to_world(origin),
+            direction: start.direction_to_world(direction),
+            ..*ray
+        };
+        //rigid moves keep distances, so t along the moved ray is the same as along the real one
+        let mut hit = self.object.intersect(&moved, t_min, t_max)?;
+        hit.location = now.point_to_world(start.point_to_local(hit.location));
+        hit.normal = now.direction_to_world(start.axes.map(|axis| dot_product(hit.normal, axis)));
+        Some(hit)
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        self.bounds
+    }
+    //the sample is taken wherever the object is at a random time while the shutter is open
+    fn sample_surface(&self, rng: &mut ThreadRng) -> Option<SurfaceSample> {
+        let sample = self.object.sample_surface(rng)?;
+        let start = self.placement(0.0);
+        let now = self.placement(rng.gen());
+        Some(SurfaceSample {
+            point: now.point_to_world(start.point_to_local(sample.point)),
+            normal: now.direction_to_world(start.axes.map(|axis| dot_product(sample.normal, axis))),
+            pdf: sample.pdf,
+        })
+    }
+}
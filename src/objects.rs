@@ -1,46 +1,177 @@
+use std::f32::consts::PI;
+use std::sync::{Arc, OnceLock};
+use rand::prelude::*;
+use super::bvh::Aabb;
+use super::texture_manager::Texture;
+
+//how far rays leaving a surface start from it, per unit of distance from the origin so it stays ahead of float error
+const SURFACE_OFFSET: f32 = 0.0001;
 
 pub struct Hit {
     pub t: f32,
     pub location: [f32; 3],
     pub normal: [f32; 3],
+    pub front_face: bool,
     pub color: [f32; 3],
-    pub light: f32,
+    pub emission: [f32; 3],
     pub smoothness: f32,
+    pub metallic: f32,
+    pub opacity: f32,
+    pub ior: f32,
+    //which scene object was hit counting from 1, filled in by the bvh the object sits in
+    pub object: u32,
+    //the same for any two materials that look alike
+    pub material: u32,
 }
 
 impl Hit {
-    pub fn new(t: f32, location: [f32; 3], normal: [f32; 3], color: [f32; 3], light: f32, smoothness: f32) -> Hit {
+    //where a ray leaving the hit along direction starts, pushed off the surface on the side it leaves from so it
+    //can't hit the same surface straight away
+    pub fn leaving_point(&self, direction: [f32; 3]) -> [f32; 3] {
+        let offset = SURFACE_OFFSET * self.location.iter().fold(1.0_f32, |largest, value| largest.max(value.abs()));
+        let offset = if dot_product(direction, self.normal) >= 0.0 { offset } else { -offset };
+        [0, 1, 2].map(|i| self.location[i] + self.normal[i] * offset)
+    }
+}
+
+//a point picked on a shape's surface, pdf is per unit area so 1 / area for uniform sampling
+pub struct SurfaceSample {
+    pub point: [f32; 3],
+    pub normal: [f32; 3],
+    pub pdf: f32,
+}
+
+//anything the renderer can shoot a ray at, the scene and bvh only see shapes through this
+pub trait Hittable: Send + Sync {
+    //closest hit with t_min < t < t_max
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit>;
+    //box around the whole shape, None for shapes that go on forever like planes
+    fn bounds(&self) -> Option<Aabb>;
+    //uniformly distributed point on the surface, None when the shape can't be sampled
+    fn sample_surface(&self, rng: &mut ThreadRng) -> Option<SurfaceSample>;
+}
+
+#[derive(Clone)]
+pub struct Material {
+    pub color: [f32; 3],
+    pub light: f32,
+    //emits this instead of color * light when set, e.g. from an .mtl Ke
+    pub emission_color: Option<[f32; 3]>,
+    pub smoothness: f32,
+    //how much the mirror part of a bounce takes on the color, 1 like metal, 0 leaves the highlight white like on plastic
+    pub metallic: f32,
+    pub opacity: f32,
+    pub ior: f32,
+    pub albedo_texture: Option<Arc<Texture>>,
+    pub emission_texture: Option<Arc<Texture>>,
+    //scaled by the roughness smoothness leaves, so with the default smoothness of 0 it's used as it is
+    pub roughness_texture: Option<Arc<Texture>>,
+    //gltf packs roughness into the green channel
+    pub roughness_channel: usize,
+    //scales metallic
+    pub metallic_texture: Option<Arc<Texture>>,
+    //and metalness into the blue one
+    pub metallic_channel: usize,
+    //worked out on the first hit, once loading has finished changing the fields
+    id: OnceLock<u32>,
+}
+impl Material {
+    pub fn new(color: [f32; 3], light: f32, smoothness: f32) -> Material {
+        Material {
+            color,
+            light,
+            emission_color: None,
+            smoothness,
+            metallic: 1.0,
+            opacity: 1.0,
+            ior: 1.5,
+            albedo_texture: None,
+            emission_texture: None,
+            roughness_texture: None,
+            roughness_channel: 0,
+            metallic_texture: None,
+            metallic_channel: 0,
+            id: OnceLock::new(),
+        }
+    }
+    //looks up the textures at the hit's uv and fills in the surface properties
+    //normal must face against the ray, front_face says whether the ray arrived from outside
+    pub fn shade(&self, t: f32, location: [f32; 3], normal: [f32; 3], front_face: bool, uv: [f32; 2]) -> Hit {
+        let mut color = self.color;
+        if let Some(texture) = &self.albedo_texture {
+            let texel = texture.sample(uv);
+            color = [color[0] * texel[0], color[1] * texel[1], color[2] * texel[2]];
+        }
+        let emission_color = match (&self.emission_texture, self.emission_color) {
+            (Some(texture), _) => texture.sample(uv),
+            (None, Some(emission_color)) => emission_color,
+            (None, None) => color,
+        };
+        let emission = [emission_color[0] * self.light, emission_color[1] * self.light, emission_color[2] * self.light];
+        let smoothness = match &self.roughness_texture {
+            Some(texture) => 1.0 - texture.sample(uv)[self.roughness_channel] * (1.0 - self.smoothness),
+            None => self.smoothness,
+        };
+        let metallic = match &self.metallic_texture {
+            Some(texture) => texture.sample(uv)[self.metallic_channel] * self.metallic,
+            None => self.metallic,
+        };
         Hit {
-            t: t,
-            location: location,
-            normal: normal,
-            color: color,
-            light: light,
-            smoothness: smoothness,
+            t,
+            location,
+            normal,
+            front_face,
+            color,
+            emission,
+            smoothness,
+            metallic,
+            opacity: self.opacity,
+            ior: self.ior,
+            object: 0,
+            material: *self.id.get_or_init(|| self.hash()),
         }
     }
-
+    //hash of everything that makes the material look the way it does, textures count by their size and a few texels
+    fn hash(&self) -> u32 {
+        let mut values = vec![self.light.to_bits(), self.smoothness.to_bits(), self.opacity.to_bits(), self.ior.to_bits(), self.roughness_channel as u32,
+            self.metallic.to_bits(), self.metallic_channel as u32];
+        values.extend(self.color.map(f32::to_bits));
+        values.extend(self.emission_color.unwrap_or([-1.0; 3]).map(f32::to_bits));
+        for texture in [&self.albedo_texture, &self.emission_texture, &self.roughness_texture, &self.metallic_texture].into_iter().flatten() {
+            values.extend([texture.width as u32, texture.height as u32]);
+            let step = (texture.data.len() / 16).max(1);
+            values.extend(texture.data.iter().step_by(step).flat_map(|texel| texel.map(f32::to_bits)));
+        }
+        //fnv-1a
+        let mut hash: u32 = 0x811c9dc5;
+        for value in values {
+            for byte in value.to_le_bytes() {
+                hash ^= byte as u32;
+                hash = hash.wrapping_mul(0x01000193);
+            }
+        }
+        //kept below 2^24 so it survives being stored as a float
+        hash & 0xffffff
+    }
 }
 
 #[derive(Clone)]
 pub struct Sphere {
     pub center: [f32; 3],
     pub radius: f32,
-    pub color: [f32; 3],
-    pub light: f32,
-    pub smoothness: f32,
+    pub material: Material,
 }
 impl Sphere {
-    pub fn new(center: [f32; 3], radius: f32, color: [f32; 3], light: f32, smoothness: f32) -> Sphere {
+    pub fn new(center: [f32; 3], radius: f32, material: Material) -> Sphere {
         Sphere {
             center,
             radius,
-            color,
-            light,
-            smoothness,
+            material,
         }
     }
-    pub fn intersection(&self, ray: &Ray) -> Hit {
+}
+impl Hittable for Sphere {
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
         let a = ray.direction[0].powi(2) + ray.direction[1].powi(2) + ray.direction[2].powi(2);
         let b = 2.0 * (ray.direction[0] * (ray.origin[0] - self.center[0]) +
                        ray.direction[1] * (ray.origin[1] - self.center[1]) +
@@ -50,13 +181,17 @@ impl Sphere {
                 (ray.origin[2] - self.center[2]).powi(2) - self.radius.powi(2);
         let discriminant = b.powi(2) - 4.0 * a * c;
         if discriminant < 0.0 {
-            return Hit::new(-1.0, [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], 0.0, 0.0);
+            return None;
+        }
+        //the far root is hit from inside
+        let mut t = (-b - discriminant.sqrt()) / (2.0 * a);
+        if t <= t_min {
+            t = (-b + discriminant.sqrt()) / (2.0 * a);
         }
-        let t = (-b - discriminant.sqrt()) / (2.0 * a);
-        if t < 0.00001 {
-            return Hit::new(-1.0, [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], 0.0, 0.0);
+        if t <= t_min || t >= t_max {
+            return None;
         }
-        let mut location = [ray.origin[0] + ray.direction[0] * t,
+        let location = [ray.origin[0] + ray.direction[0] * t,
                         ray.origin[1] + ray.direction[1] * t,
                         ray.origin[2] + ray.direction[2] * t];
         let location = [location[0] - self.center[0], location[1] - self.center[1], location[2] - self.center[2]];
@@ -67,37 +202,149 @@ impl Sphere {
         let normal = [(location[0] - self.center[0]) / self.radius,
                         (location[1] - self.center[1]) / self.radius,
                         (location[2] - self.center[2]) / self.radius];
-        
-        Hit::new(t, location, normal, self.color, self.light, self.smoothness)
+        let uv = [0.5 + normal[2].atan2(normal[0]) / (2.0 * PI), 0.5 + normal[1].clamp(-1.0, 1.0).asin() / PI];
+
+        if dot_product(ray.direction, normal) > 0.0 {
+            return Some(self.material.shade(t, location, [-normal[0], -normal[1], -normal[2]], false, uv));
+        }
+        Some(self.material.shade(t, location, normal, true, uv))
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        let radius = self.radius.abs();
+        Some(Aabb::new(self.center.map(|value| value - radius), self.center.map(|value| value + radius)))
+    }
+    fn sample_surface(&self, rng: &mut ThreadRng) -> Option<SurfaceSample> {
+        let y = 1.0 - 2.0 * rng.gen::<f32>();
+        let ring = (1.0 - y * y).max(0.0).sqrt();
+        let angle = 2.0 * PI * rng.gen::<f32>();
+        let normal = [ring * angle.cos(), y, ring * angle.sin()];
+        Some(SurfaceSample {
+            point: [self.center[0] + normal[0] * self.radius, self.center[1] + normal[1] * self.radius, self.center[2] + normal[2] * self.radius],
+            normal,
+            pdf: 1.0 / (4.0 * PI * self.radius * self.radius),
+        })
     }
 }
 
-fn cross_product(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+#[derive(Clone, Copy)]
+pub enum Pattern {
+    None,
+    //squares of size scale alternating between the material color and color
+    Checker { scale: f32, color: [f32; 3] },
+    //lines of line_width every scale units drawn in color
+    Grid { scale: f32, line_width: f32, color: [f32; 3] },
+}
+
+impl Pattern {
+    fn color_at(&self, coordinates: [f32; 2]) -> Option<[f32; 3]> {
+        match *self {
+            Pattern::None => None,
+            Pattern::Checker { scale, color } => {
+                let cell = (coordinates[0] / scale).floor() + (coordinates[1] / scale).floor();
+                if cell.rem_euclid(2.0) >= 1.0 { Some(color) } else { None }
+            }
+            Pattern::Grid { scale, line_width, color } => {
+                let distance = |value: f32| {
+                    let offset = (value / scale).rem_euclid(1.0) * scale;
+                    offset.min(scale - offset)
+                };
+                if distance(coordinates[0]) < line_width / 2.0 || distance(coordinates[1]) < line_width / 2.0 { Some(color) } else { None }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Plane {
+    pub point: [f32; 3],
+    pub normal: [f32; 3],
+    //directions along the plane used for uvs and patterns
+    pub tangent: [f32; 3],
+    pub bitangent: [f32; 3],
+    pub material: Material,
+    pub pattern: Pattern,
+}
+impl Plane {
+    pub fn new(point: [f32; 3], normal: [f32; 3], material: Material, pattern: Pattern) -> Plane {
+        let normal = normalize(normal);
+        //any axis that isn't close to the normal gives a stable tangent
+        let axis = if normal[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 0.0, 1.0] };
+        let bitangent = normalize(cross_product(normal, axis));
+        let tangent = cross_product(bitangent, normal);
+        Plane {
+            point,
+            normal,
+            tangent,
+            bitangent,
+            material,
+            pattern,
+        }
+    }
+}
+impl Hittable for Plane {
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+        let normal_dot_dir = dot_product(self.normal, ray.direction);
+        if normal_dot_dir.abs() < 0.00001 { //parrallel check
+            return None;
+        }
+        let t = dot_product(self.normal, subtract(self.point, ray.origin)) / normal_dot_dir;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+        let location = [ray.origin[0] + ray.direction[0] * t,
+                        ray.origin[1] + ray.direction[1] * t,
+                        ray.origin[2] + ray.direction[2] * t];
+        let offset = subtract(location, self.point);
+        let coordinates = [dot_product(offset, self.tangent), dot_product(offset, self.bitangent)];
+
+        let front_face = normal_dot_dir < 0.0;
+        let normal = if front_face { self.normal } else { [-self.normal[0], -self.normal[1], -self.normal[2]] };
+        let mut hit = self.material.shade(t, location, normal, front_face, coordinates);
+        if let Some(color) = self.pattern.color_at(coordinates) {
+            hit.color = color;
+        }
+        Some(hit)
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        None
+    }
+    fn sample_surface(&self, _rng: &mut ThreadRng) -> Option<SurfaceSample> {
+        None
+    }
+}
+
+pub fn cross_product(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
     [a[1] * b[2] - a[2] * b[1],
      a[2] * b[0] - a[0] * b[2],
      a[0] * b[1] - a[1] * b[0]]
 }
-fn dot_product(a: [f32; 3], b: [f32; 3]) -> f32 {
+pub fn dot_product(a: [f32; 3], b: [f32; 3]) -> f32 {
     a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
 }
-fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
-    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
-}
-fn subtract(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+pub fn subtract(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
     [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
 }
+pub fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let length = dot_product(a, a).sqrt();
+    if length == 0.0 {
+        return a;
+    }
+    [a[0] / length, a[1] / length, a[2] / length]
+}
 #[derive(Clone)]
 
 pub struct Triangle {
     pub vertices: [[f32; 3]; 3],
     pub normal: [f32; 3],
-    pub color: [f32; 3],
-    pub light: f32,
-    pub smoothness: f32,
+    pub uvs: [[f32; 2]; 3],
+    //smooth shading and vertex colors, interpolated across the face when present
+    pub vertex_normals: Option<[[f32; 3]; 3]>,
+    pub vertex_colors: Option<[[f32; 3]; 3]>,
+    pub material: Material,
 }
 
 impl Triangle {
-    pub fn new(vertices: [[f32; 3]; 3], color: [f32; 3], light: f32, smoothness: f32) -> Triangle {
+    pub fn new(vertices: [[f32; 3]; 3], uvs: [[f32; 2]; 3], material: Material) -> Triangle {
         let normal = [(vertices[1][1] - vertices[0][1]) * (vertices[2][2] - vertices[0][2]) - (vertices[1][2] - vertices[0][2]) * (vertices[2][1] - vertices[0][1]),
                         (vertices[1][2] - vertices[0][2]) * (vertices[2][0] - vertices[0][0]) - (vertices[1][0] - vertices[0][0]) * (vertices[2][2] - vertices[0][2]),
                         (vertices[1][0] - vertices[0][0]) * (vertices[2][1] - vertices[0][1]) - (vertices[1][1] - vertices[0][1]) * (vertices[2][0] - vertices[0][0])];
@@ -106,12 +353,15 @@ impl Triangle {
         Triangle {
             vertices,
             normal,
-            color,
-            light,
-            smoothness,
+            uvs,
+            vertex_normals: None,
+            vertex_colors: None,
+            material,
         }
     }
-    pub fn intersection(&self, ray: &Ray) -> Hit {
+}
+impl Hittable for Triangle {
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
 
         //converted math into rust
         //https://diegoinacio.github.io/computer-vision-notebooks-page/pages/ray-intersection_triangle.html 
@@ -125,13 +375,13 @@ impl Triangle {
         let normal_dot_dir = dot_product(self.normal, ray.direction);
 
         if normal_dot_dir.abs() < 0.00001 { //parrallel check
-            return Hit::new(-1.0, [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], 0.0, 0.0);
+            return None;
         }
 
         let normal_dot_origin = dot_product(self.normal, ray.origin);
         let t = -(normal_dot_origin + d) / normal_dot_dir;
-        if t < 0.00001 {
-            return Hit::new(-1.0, [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], 0.0, 0.0);
+        if t <= t_min || t >= t_max {
+            return None;
         }
         let p = [ray.origin[0] + ray.direction[0] * t,
                  ray.origin[1] + ray.direction[1] * t,
@@ -141,12 +391,58 @@ impl Triangle {
         let pc = dot_product(cross_product(ca, subtract(p, self.vertices[2])), self.normal);
 
         if pa >= 0.0 && pb >= 0.0 && pc >= 0.0 {
-            if dot_product(ray.direction, self.normal) > 0.0 {
-                return Hit::new(t, p, [-self.normal[0], -self.normal[1], -self.normal[2]], self.color, self.light, self.smoothness);
+            //pa, pb and pc are twice the areas opposite vertices 2, 0 and 1
+            let area = pa + pb + pc;
+            let (w0, w1, w2) = (pb / area, pc / area, pa / area);
+            let uv = [self.uvs[0][0] * w0 + self.uvs[1][0] * w1 + self.uvs[2][0] * w2,
+                      self.uvs[0][1] * w0 + self.uvs[1][1] * w1 + self.uvs[2][1] * w2];
+            let mut normal = self.normal;
+            if let Some(normals) = self.vertex_normals {
+                let interpolated = [normals[0][0] * w0 + normals[1][0] * w1 + normals[2][0] * w2,
+                                    normals[0][1] * w0 + normals[1][1] * w1 + normals[2][1] * w2,
+                                    normals[0][2] * w0 + normals[1][2] * w1 + normals[2][2] * w2];
+                let length = dot_product(interpolated, interpolated).sqrt();
+                if length > 0.0 {
+                    //keep the shading normal on the same side as the face
+                    let side = dot_product(interpolated, self.normal).signum();
+                    normal = [interpolated[0] / length * side, interpolated[1] / length * side, interpolated[2] / length * side];
+                }
             }
-            return Hit::new(t, p, self.normal, self.color, self.light, self.smoothness);
+            let front_face = dot_product(ray.direction, self.normal) <= 0.0;
+            if !front_face {
+                normal = [-normal[0], -normal[1], -normal[2]];
+            }
+            let mut hit = self.material.shade(t, p, normal, front_face, uv);
+            if let Some(colors) = self.vertex_colors {
+                let tint = [colors[0][0] * w0 + colors[1][0] * w1 + colors[2][0] * w2,
+                            colors[0][1] * w0 + colors[1][1] * w1 + colors[2][1] * w2,
+                            colors[0][2] * w0 + colors[1][2] * w1 + colors[2][2] * w2];
+                hit.color = [hit.color[0] * tint[0], hit.color[1] * tint[1], hit.color[2] * tint[2]];
+                hit.emission = [hit.emission[0] * tint[0], hit.emission[1] * tint[1], hit.emission[2] * tint[2]];
+            }
+            return Some(hit);
+        }
+        None
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::from_points(&self.vertices))
+    }
+    fn sample_surface(&self, rng: &mut ThreadRng) -> Option<SurfaceSample> {
+        let edge_1 = subtract(self.vertices[1], self.vertices[0]);
+        let edge_2 = subtract(self.vertices[2], self.vertices[0]);
+        let area = dot_product(cross_product(edge_1, edge_2), cross_product(edge_1, edge_2)).sqrt() / 2.0;
+        //folding the square along its diagonal keeps the points uniform
+        let (mut u, mut v) = (rng.gen::<f32>(), rng.gen::<f32>());
+        if u + v > 1.0 {
+            (u, v) = (1.0 - u, 1.0 - v);
         }
-        Hit::new(-1.0, [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], 0.0, 0.0)
+        Some(SurfaceSample {
+            point: [self.vertices[0][0] + edge_1[0] * u + edge_2[0] * v,
+                    self.vertices[0][1] + edge_1[1] * u + edge_2[1] * v,
+                    self.vertices[0][2] + edge_1[2] * u + edge_2[2] * v],
+            normal: self.normal,
+            pdf: 1.0 / area,
+        })
     }
 }
 
@@ -193,11 +489,54 @@ impl TriangleObject {
     }
 }
 */
+//x is right, y is up and z is forward in the camera's own frame
+#[derive(Clone)]
+pub struct Camera {
+    pub position: [f32; 3],
+    pub right: [f32; 3],
+    pub up: [f32; 3],
+    pub forward: [f32; 3],
+    //horizontal field of view in radians, the renderer's default is used when unset
+    pub fov: Option<f32>,
+}
+impl Camera {
+    pub fn new(position: [f32; 3]) -> Camera {
+        Camera {
+            position,
+            right: [1.0, 0.0, 0.0],
+            up: [0.0, 1.0, 0.0],
+            forward: [0.0, 0.0, 1.0],
+            fov: None,
+        }
+    }
+    pub fn look_at(position: [f32; 3], target: [f32; 3], up: [f32; 3]) -> Camera {
+        let forward = normalize(subtract(target, position));
+        let right = normalize(cross_product(up, forward));
+        let up = cross_product(forward, right);
+        Camera {
+            position,
+            right,
+            up,
+            forward,
+            fov: None,
+        }
+    }
+    //turns a direction in the camera's frame into world space
+    pub fn orient(&self, direction: [f32; 3]) -> [f32; 3] {
+        [
+            self.right[0] * direction[0] + self.up[0] * direction[1] + self.forward[0] * direction[2],
+            self.right[1] * direction[0] + self.up[1] * direction[1] + self.forward[1] * direction[2],
+            self.right[2] * direction[0] + self.up[2] * direction[1] + self.forward[2] * direction[2],
+        ]
+    }
+}
+
 pub struct Ray {
     pub origin: [f32; 3],
     pub direction: [f32; 3],
     pub color: [f32; 3],
-    pub light: f32,
+    //when the ray was sent, 0 as the shutter opens and 1 as it closes, moving objects are hit where they were then
+    pub time: f32,
 }
 impl Ray {
     pub fn new(origin: [f32; 3], direction: [f32; 3]) -> Ray {
@@ -205,7 +544,7 @@ impl Ray {
             origin,
             direction,
             color: [1.0, 1.0, 1.0],
-            light: 0.0,
+            time: 0.0,
         }
     }
 }
\ No newline at end of file
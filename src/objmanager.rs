@@ -1,65 +1,483 @@
+use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
 use super::objects;
-//read from .obj file
-pub fn extract_triangles(filename: &str, translation: [f32; 3], scale: [f32; 3], light: f32, color: [f32; 3], smoothness: f32) -> Vec<objects::Triangle> {
+use super::primitives::Transform;
+use super::texture_manager::{ColorSpace, Texture, TextureCache, WrapMode};
+
+pub struct MeshError {
+    pub filename: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl MeshError {
+    pub fn new(filename: &str, line: usize, message: String) -> MeshError {
+        MeshError {
+            filename: filename.to_string(),
+            line,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        //line 0 is used for errors that don't belong to a line, like a missing file or binary data
+        if self.line == 0 {
+            return write!(f, "{}: {}", self.filename, self.message);
+        }
+        write!(f, "{}:{}: {}", self.filename, self.line, self.message)
+    }
+}
+
+impl fmt::Debug for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+fn parse_number<'a, T: FromStr>(words: &mut impl Iterator<Item = &'a str>) -> Result<T, String> {
+    let word = words.next().ok_or("missing number")?;
+    word.parse().map_err(|_| format!("invalid number '{}'", word))
+}
+
+fn parse_color<'a>(words: &mut impl Iterator<Item = &'a str>) -> Result<[f32; 3], String> {
+    let r: f32 = parse_number(words)?;
+    //a single value is a grey
+    let mut rest = words.peekable();
+    let g: f32 = if rest.peek().is_some() { parse_number(&mut rest)? } else { r };
+    let b: f32 = if rest.peek().is_some() { parse_number(&mut rest)? } else { g };
+    Ok([r, g, b])
+}
+
+//obj indices start at 1, negative ones count back from the last element read so far
+fn resolve_index(word: &str, count: usize) -> Result<usize, String> {
+    let index: i64 = word.parse().map_err(|_| format!("invalid index '{}'", word))?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!("index {} out of range, {} defined", index, count));
+    }
+    Ok(resolved as usize)
+}
+
+fn polygon_normal(points: &[[f32; 3]]) -> [f32; 3] {
+    //newell's method, robust for non planar and concave polygons
+    let mut normal = [0.0, 0.0, 0.0];
+    for i in 0..points.len() {
+        let current = points[i];
+        let next = points[(i + 1) % points.len()];
+        normal[0] += (current[1] - next[1]) * (current[2] + next[2]);
+        normal[1] += (current[2] - next[2]) * (current[0] + next[0]);
+        normal[2] += (current[0] - next[0]) * (current[1] + next[1]);
+    }
+    normal
+}
+
+//ear clipping so concave polygons stay inside their outline, falls back to a fan when the polygon is degenerate
+pub fn triangulate(points: &[[f32; 3]]) -> Vec<[usize; 3]> {
+    if points.len() == 3 {
+        return vec![[0, 1, 2]];
+    }
+    //flatten onto the plane the polygon mostly faces
+    let normal = polygon_normal(points);
+    let axis = (0..3).max_by(|&a, &b| normal[a].abs().total_cmp(&normal[b].abs())).unwrap();
+    let (u, v) = match axis {
+        0 => (1, 2),
+        1 => (2, 0),
+        _ => (0, 1),
+    };
+    let flat: Vec<[f32; 2]> = points.iter().map(|point| [point[u], point[v]]).collect();
+    let orientation = normal[axis].signum();
+    let cross = |a: [f32; 2], b: [f32; 2], c: [f32; 2]| ((b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])) * orientation;
+
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
     let mut triangles = vec![];
-    let mut vertices = vec![];
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let ear = (0..count).find(|&i| {
+            let a = flat[remaining[(i + count - 1) % count]];
+            let b = flat[remaining[i]];
+            let c = flat[remaining[(i + 1) % count]];
+            if cross(a, b, c) <= 0.0 {
+                return false;
+            }
+            remaining.iter().all(|&other| {
+                let p = flat[other];
+                p == a || p == b || p == c || cross(a, b, p) < 0.0 || cross(b, c, p) < 0.0 || cross(c, a, p) < 0.0
+            })
+        });
+        let Some(ear) = ear else {
+            break;
+        };
+        triangles.push([remaining[(ear + count - 1) % count], remaining[ear], remaining[(ear + 1) % count]]);
+        remaining.remove(ear);
+    }
+    if remaining.len() == 3 {
+        triangles.push([remaining[0], remaining[1], remaining[2]]);
+    } else {
+        for i in 1..remaining.len() - 1 {
+            triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+        }
+    }
+    triangles
+}
 
-    let file = File::open(filename).unwrap();
-    let reader = BufReader::new(file);
-    let mut minmaxx = [0.0, 0.0];
-    let mut minmaxy = [0.0, 0.0];
-    let mut minmaxz = [0.0, 0.0];
+#[derive(Clone, Copy, PartialEq)]
+pub enum Alignment {
+    //keep the coordinates the mesh was authored in
+    None,
+    Center,
+    //centered horizontally with the lowest point on y = 0
+    Bottom,
+}
+
+impl Alignment {
+    pub fn from_name(name: &str) -> Alignment {
+        match name {
+            "center" => Alignment::Center,
+            "bottom" => Alignment::Bottom,
+            _ => Alignment::None,
+        }
+    }
+}
 
-    for line in reader.lines() {
-        let line = line.unwrap();
+//where a loaded mesh ends up in the scene, applied as align, fit, scale, rotate then translate
+pub struct Placement {
+    pub translation: [f32; 3],
+    pub scale: [f32; 3],
+    //degrees around x, then y, then z like shapes, so the mesh turns about its translation
+    pub rotation: [f32; 3],
+    pub alignment: Alignment,
+    //largest side of the bounding box after fitting, before scale
+    pub fit_size: Option<f32>,
+}
+
+impl Placement {
+    pub fn new(translation: [f32; 3], scale: [f32; 3]) -> Placement {
+        Placement {
+            translation,
+            scale,
+            rotation: [0.0, 0.0, 0.0],
+            alignment: Alignment::None,
+            fit_size: None,
+        }
+    }
+    //maps points from the mesh's own space into the scene, the bounds of the mesh set the alignment and fit
+    pub fn mapping(&self, triangles: &[objects::Triangle]) -> impl Fn([f32; 3]) -> [f32; 3] + '_ {
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        for triangle in triangles {
+            for vertex in &triangle.vertices {
+                for j in 0..3 {
+                    min[j] = min[j].min(vertex[j]);
+                    max[j] = max[j].max(vertex[j]);
+                }
+            }
+        }
+        let mut anchor = [0.0, 0.0, 0.0];
+        let mut fit = 1.0;
+        if !triangles.is_empty() {
+            let mid_point = [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0, (min[2] + max[2]) / 2.0];
+            anchor = match self.alignment {
+                Alignment::None => [0.0, 0.0, 0.0],
+                Alignment::Center => mid_point,
+                Alignment::Bottom => [mid_point[0], min[1], mid_point[2]],
+            };
+            let largest_side = (max[0] - min[0]).max(max[1] - min[1]).max(max[2] - min[2]);
+            if let Some(size) = self.fit_size.filter(|_| largest_side > 0.0) {
+                fit = size / largest_side;
+            }
+        }
+        let transform = Transform::new(self.translation, self.rotation);
+        move |point: [f32; 3]| transform.point_to_world([
+            (point[0] - anchor[0]) * fit * self.scale[0],
+            (point[1] - anchor[1]) * fit * self.scale[1],
+            (point[2] - anchor[2]) * fit * self.scale[2],
+        ])
+    }
+    //turns a direction like the mesh is turned, without scaling it
+    pub fn rotate(&self, direction: [f32; 3]) -> [f32; 3] {
+        Transform::new([0.0, 0.0, 0.0], self.rotation).direction_to_world(direction)
+    }
+    pub fn apply(&self, triangles: Vec<objects::Triangle>) -> Vec<objects::Triangle> {
+        let place = self.mapping(&triangles);
+        let transform = Transform::new([0.0, 0.0, 0.0], self.rotation);
+
+        //rebuilt rather than moved in place so the face normals follow non uniform scales
+        triangles.into_iter().map(|triangle| {
+            let vertices = triangle.vertices.map(&place);
+            let mut placed = objects::Triangle::new(vertices, triangle.uvs, triangle.material);
+            //normals use the inverse scale so they stay perpendicular to the stretched surface
+            placed.vertex_normals = triangle.vertex_normals.map(|normals| normals.map(|normal| {
+                let scaled = [normal[0] / self.scale[0], normal[1] / self.scale[1], normal[2] / self.scale[2]];
+                let length = (scaled[0].powi(2) + scaled[1].powi(2) + scaled[2].powi(2)).sqrt();
+                transform.direction_to_world([scaled[0] / length, scaled[1] / length, scaled[2] / length])
+            }));
+            placed.vertex_colors = triangle.vertex_colors;
+            placed
+        }).collect()
+    }
+}
+
+//a material partway through being read from an .mtl file, Ks, Ns and Pr only become smoothness once it's finished
+struct MtlMaterial {
+    name: String,
+    material: objects::Material,
+    specular: [f32; 3],
+    exponent: f32,
+    roughness: Option<f32>,
+}
+
+impl MtlMaterial {
+    fn new(name: String) -> MtlMaterial {
+        MtlMaterial {
+            name,
+            material: objects::Material::new([1.0, 1.0, 1.0], 0.0, 0.0),
+            specular: [1.0, 1.0, 1.0],
+            exponent: 0.0,
+            roughness: None,
+        }
+    }
+    //blender writes Ns = (1 - roughness)^2 * 1000, no specular colour means a purely diffuse surface
+    fn finish(self, smoothness: bool) -> (String, objects::Material) {
+        let mut material = self.material;
+        if !smoothness {
+            material.roughness_texture = None;
+            return (self.name, material);
+        }
+        material.smoothness = match self.roughness {
+            _ if self.specular.iter().all(|&channel| channel <= 0.0) => 0.0,
+            Some(roughness) => 1.0 - roughness,
+            None => (self.exponent / 1000.0_f32).clamp(0.0, 1.0).sqrt(),
+        };
+        (self.name, material)
+    }
+}
+
+//options such as -s come before the filename, so only the last word is used
+fn load_texture<'w>(directory: &Path, textures: &mut TextureCache, words: impl Iterator<Item = &'w str>, color_space: ColorSpace) -> Result<Arc<Texture>, String> {
+    let path = directory.join(words.last().ok_or("missing texture filename")?);
+    let path = path.to_string_lossy();
+    textures.load(&path, WrapMode::Repeat, color_space).map_err(|error| format!("texture {}: {}", path, error))
+}
+
+struct MtlReader<'a> {
+    directory: &'a Path,
+    smoothness: bool,
+    textures: &'a mut TextureCache,
+    current: Option<MtlMaterial>,
+    materials: HashMap<String, objects::Material>,
+}
+
+impl MtlReader<'_> {
+    fn finish_material(&mut self) {
+        if let Some(current) = self.current.take() {
+            let (name, material) = current.finish(self.smoothness);
+            self.materials.insert(name, material);
+        }
+    }
+    fn read_line(&mut self, line: &str) -> Result<(), String> {
+        let mut words = line.split_whitespace();
+        let keyword = words.next();
+        if keyword == Some("newmtl") {
+            self.finish_material();
+            self.current = Some(MtlMaterial::new(words.collect::<Vec<&str>>().join(" ")));
+            return Ok(());
+        }
+        //anything before the first newmtl has no material to go to
+        let Some(current) = &mut self.current else {
+            return Ok(());
+        };
+        let material = &mut current.material;
+        match keyword {
+            Some("Kd") => material.color = parse_color(&mut words)?,
+            Some("Ks") => current.specular = parse_color(&mut words)?,
+            Some("Ke") => {
+                let emission = parse_color(&mut words)?;
+                if emission.iter().any(|&channel| channel > 0.0) {
+                    material.emission_color = Some(emission);
+                    material.light = 1.0;
+                }
+            }
+            Some("Ns") => current.exponent = parse_number(&mut words)?,
+            Some("Pr") => current.roughness = Some(parse_number(&mut words)?),
+            Some("Ni") => material.ior = parse_number(&mut words)?,
+            Some("d") => material.opacity = parse_number(&mut words)?,
+            Some("Tr") => material.opacity = 1.0 - parse_number::<f32>(&mut words)?,
+            Some("map_Kd") => {
+                material.albedo_texture = Some(load_texture(self.directory, self.textures, words, ColorSpace::Srgb)?);
+            }
+            Some("map_Ke") => {
+                material.emission_texture = Some(load_texture(self.directory, self.textures, words, ColorSpace::Srgb)?);
+                material.light = material.light.max(1.0);
+            }
+            Some("map_Pr") => {
+                material.roughness_texture = Some(load_texture(self.directory, self.textures, words, ColorSpace::Linear)?);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+//read materials from an .mtl file, paths inside it are relative to the file. exporters write a shininess for
+//surfaces that were never meant to be glossy, so Ns, Pr and map_Pr are only read when smoothness is asked for
+fn extract_materials(filename: &Path, smoothness: bool, textures: &mut TextureCache) -> Result<HashMap<String, objects::Material>, MeshError> {
+    let display_name = filename.to_string_lossy();
+    let file = File::open(filename).map_err(|error| MeshError::new(&display_name, 0, error.to_string()))?;
+    let mut reader = MtlReader {
+        directory: filename.parent().unwrap_or(Path::new("")),
+        smoothness,
+        textures,
+        current: None,
+        materials: HashMap::new(),
+    };
+    for (line_number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|error| MeshError::new(&display_name, line_number + 1, error.to_string()))?;
+        reader.read_line(&line).map_err(|message| MeshError::new(&display_name, line_number + 1, message))?;
+    }
+    reader.finish_material();
+    Ok(reader.materials)
+}
+
+//everything read from an .obj file so far
+struct ObjReader<'a, F: Fn(&mut objects::Material)> {
+    filename: &'a str,
+    groups: Option<&'a [String]>,
+    mtl_smoothness: bool,
+    textures: &'a mut TextureCache,
+    override_material: F,
+    vertices: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    normals: Vec<[f32; 3]>,
+    materials: HashMap<String, objects::Material>,
+    default_material: objects::Material,
+    material: objects::Material,
+    current_object: String,
+    current_groups: Vec<String>,
+    triangles: Vec<objects::Triangle>,
+}
+
+impl<F: Fn(&mut objects::Material)> ObjReader<'_, F> {
+    fn read_line(&mut self, line: &str) -> Result<(), String> {
         let mut words = line.split_whitespace();
         match words.next() {
             Some("v") => {
-                let x: f32 = words.next().unwrap().parse().unwrap();
-                let y: f32 = words.next().unwrap().parse().unwrap();
-                let z: f32 = words.next().unwrap().parse().unwrap();
-                if x < minmaxx[0] {
-                    minmaxx[0] = x;
-                }
-                if x > minmaxx[1] {
-                    minmaxx[1] = x;
-                }
-                if y < minmaxy[0] {
-                    minmaxy[0] = y;
-                }
-                if y > minmaxy[1] {
-                    minmaxy[1] = y;
-                }
-                if z < minmaxz[0] {
-                    minmaxz[0] = z;
-                }
-                if z > minmaxz[1] {
-                    minmaxz[1] = z;
-                }
-                vertices.push([x, y, z]);
-            }
-            Some("f") => {
-                let mut face = [[0.0; 3]; 3];
-                for i in 0..3 {
-                    //println!("{}", words.next().unwrap().split("/").collect::<Vec<&str>>()[0].parse().unwrap());
-                    let index: usize = words.next().unwrap().split("/").collect::<Vec<&str>>()[0].parse().unwrap();
-                    face[i] = vertices[index - 1];
+                let x: f32 = parse_number(&mut words)?;
+                let y: f32 = parse_number(&mut words)?;
+                let z: f32 = parse_number(&mut words)?;
+                self.vertices.push([x, y, z]);
+            }
+            Some("vt") => {
+                let u: f32 = parse_number(&mut words)?;
+                let v: f32 = match words.next() {
+                    Some(v) => v.parse().map_err(|_| format!("invalid number '{}'", v))?,
+                    None => 0.0,
+                };
+                self.uvs.push([u, v]);
+            }
+            Some("vn") => {
+                let x: f32 = parse_number(&mut words)?;
+                let y: f32 = parse_number(&mut words)?;
+                let z: f32 = parse_number(&mut words)?;
+                self.normals.push([x, y, z]);
+            }
+            Some("o") => self.current_object = words.collect::<Vec<&str>>().join(" "),
+            Some("g") => self.current_groups = words.map(|group| group.to_string()).collect(),
+            Some("mtllib") => {
+                let directory = Path::new(self.filename).parent().unwrap_or(Path::new(""));
+                for library in words {
+                    let library_materials = extract_materials(&directory.join(library), self.mtl_smoothness, self.textures).map_err(|error| error.to_string())?;
+                    for (name, mut library_material) in library_materials {
+                        (self.override_material)(&mut library_material);
+                        self.materials.insert(name, library_material);
+                    }
                 }
-                triangles.push(objects::Triangle::new(face, color, light, smoothness));
             }
+            Some("usemtl") => {
+                let name = words.collect::<Vec<&str>>().join(" ");
+                self.material = self.materials.get(&name).unwrap_or(&self.default_material).clone();
+            }
+            Some("f") => self.read_face(words)?,
             _ => {}
         }
+        Ok(())
     }
-    
-    let mid_point = [(minmaxx[0] + minmaxx[1]) / 2.0, (minmaxy[0] + minmaxy[1]) / 2.0, (minmaxz[0] + minmaxz[1]) / 2.0];
-    for triangle in &mut triangles {
-        for i in 0..3 {
-            for j in 0..3 {
-                triangle.vertices[i][j] = (triangle.vertices[i][j]/*- mid_point[j]*/) * scale[j] + translation[j];
+
+    //corners are v, v/vt, v//vn or v/vt/vn
+    fn read_face<'w>(&mut self, words: impl Iterator<Item = &'w str>) -> Result<(), String> {
+        let mut points = vec![];
+        let mut point_uvs = vec![];
+        let mut point_normals = vec![];
+        for word in words {
+            let indices = word.split("/").collect::<Vec<&str>>();
+            points.push(self.vertices[resolve_index(indices[0], self.vertices.len())?]);
+            match indices.get(1).filter(|index| !index.is_empty()) {
+                Some(uv_index) => point_uvs.push(Some(self.uvs[resolve_index(uv_index, self.uvs.len())?])),
+                None => point_uvs.push(None),
+            }
+            match indices.get(2).filter(|index| !index.is_empty()) {
+                Some(normal_index) => point_normals.push(Some(self.normals[resolve_index(normal_index, self.normals.len())?])),
+                None => point_normals.push(None),
+            }
+        }
+        if points.len() < 3 {
+            return Err(format!("face needs at least 3 vertices, found {}", points.len()));
+        }
+        if let Some(groups) = self.groups {
+            let in_group = groups.contains(&self.current_object) || self.current_groups.iter().any(|group| groups.contains(group));
+            if !in_group {
+                return Ok(());
             }
         }
+        for corners in triangulate(&points) {
+            let face = corners.map(|corner| points[corner]);
+            let mut face_uvs = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]];
+            if let [Some(a), Some(b), Some(c)] = corners.map(|corner| point_uvs[corner]) {
+                face_uvs = [a, b, c];
+            }
+            let mut triangle = objects::Triangle::new(face, face_uvs, self.material.clone());
+            if let [Some(a), Some(b), Some(c)] = corners.map(|corner| point_normals[corner]) {
+                triangle.vertex_normals = Some([a, b, c].map(objects::normalize));
+            }
+            self.triangles.push(triangle);
+        }
+        Ok(())
+    }
+}
+
+//read from .obj file, faces take their material from usemtl and the scene entry can override it
+//when groups is given only faces inside one of the named o/g groups are kept, mtl_smoothness reads the .mtl shininess
+pub fn extract_triangles<F: Fn(&mut objects::Material)>(filename: &str, placement: &Placement, groups: Option<&[String]>, mtl_smoothness: bool, textures: &mut TextureCache, override_material: F) -> Result<Vec<objects::Triangle>, MeshError> {
+    let mut default_material = objects::Material::new([1.0, 1.0, 1.0], 0.0, 0.0);
+    override_material(&mut default_material);
+    let file = File::open(filename).map_err(|error| MeshError::new(filename, 0, error.to_string()))?;
+    let mut reader = ObjReader {
+        filename,
+        groups,
+        mtl_smoothness,
+        textures,
+        override_material,
+        vertices: vec![],
+        uvs: vec![],
+        normals: vec![],
+        materials: HashMap::new(),
+        material: default_material.clone(),
+        default_material,
+        current_object: String::new(),
+        current_groups: vec![],
+        triangles: vec![],
+    };
+    for (line_number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|error| MeshError::new(filename, line_number + 1, error.to_string()))?;
+        reader.read_line(&line).map_err(|message| MeshError::new(filename, line_number + 1, message))?;
     }
 
     /*
@@ -68,5 +486,94 @@ pub fn extract_triangles(filename: &str, translation: [f32; 3], scale: [f32; 3],
     }
     */
 
-    triangles
-}
\ No newline at end of file
+    Ok(placement.apply(reader.triangles))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    //writes a file under the temp directory, named after the test so tests running at once don't share it
+    fn fixture(name: &str, extension: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("objmanager_{}_{}.{}", name, std::process::id(), extension));
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn load(name: &str, contents: &str, groups: Option<&[String]>) -> Result<Vec<objects::Triangle>, MeshError> {
+        let filename = fixture(name, "obj", contents);
+        let triangles = extract_triangles(&filename, &Placement::new([0.0; 3], [1.0; 3]), groups, false, &mut TextureCache::new(), |_| {});
+        fs::remove_file(&filename).unwrap();
+        triangles
+    }
+
+    fn area(triangle: &objects::Triangle) -> f32 {
+        let [a, b, c] = triangle.vertices;
+        let cross = objects::cross_product(objects::subtract(b, a), objects::subtract(c, a));
+        objects::dot_product(cross, cross).sqrt() / 2.0
+    }
+
+    #[test]
+    fn quads_are_split_in_two() {
+        let triangles = load("quad", "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n", None).unwrap();
+        assert_eq!(triangles.len(), 2);
+        assert!((triangles.iter().map(area).sum::<f32>() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn concave_faces_stay_inside_their_outline() {
+        //an L with the notch at x > 1, y > 1
+        let triangles = load("concave", "v 0 0 0\nv 2 0 0\nv 2 1 0\nv 1 1 0\nv 1 2 0\nv 0 2 0\nf 1 2 3 4 5 6\n", None).unwrap();
+        assert_eq!(triangles.len(), 4);
+        assert!((triangles.iter().map(area).sum::<f32>() - 3.0).abs() < 1e-6);
+        for triangle in &triangles {
+            let centre = [0, 1].map(|i| triangle.vertices.iter().map(|vertex| vertex[i]).sum::<f32>() / 3.0);
+            assert!(centre[0] < 1.0 || centre[1] < 1.0, "triangle {:?} covers the notch", triangle.vertices);
+        }
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_vertex() {
+        let triangles = load("relative", "v 9 9 9\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\nv 0 0 1\nf 2 -3 -1\n", None).unwrap();
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(triangles[0].vertices, [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+        assert_eq!(triangles[1].vertices, [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]]);
+    }
+
+    #[test]
+    fn groups_keep_only_their_faces() {
+        let contents = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\no body\nf 1 2 3\ng wheel hub\nf 1 2 4\ng other\nf 1 3 4\n";
+        let by_object = load("object", contents, Some(&["body".to_string()])).unwrap();
+        //the o name still applies to faces after a g line
+        assert_eq!(by_object.len(), 3);
+        let by_group = load("group", contents, Some(&["hub".to_string()])).unwrap();
+        assert_eq!(by_group.len(), 1);
+        assert_eq!(by_group[0].vertices[2], [0.0, 0.0, 1.0]);
+        let missing = load("missing", contents, Some(&["nothing".to_string()])).unwrap();
+        assert!(missing.is_empty());
+    }
+
+    #[test]
+    fn errors_name_the_file_and_line() {
+        let filename = fixture("error", "obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\n\nf 1 2 7\n");
+        let error = extract_triangles(&filename, &Placement::new([0.0; 3], [1.0; 3]), None, false, &mut TextureCache::new(), |_| {}).err().unwrap();
+        fs::remove_file(&filename).unwrap();
+        assert_eq!(error.filename, filename);
+        assert_eq!(error.line, 5);
+        assert_eq!(error.message, "index 7 out of range, 3 defined");
+    }
+
+    #[test]
+    fn missing_mtl_textures_name_the_mtl_line() {
+        let mtl = fixture("texture", "mtl", "newmtl paint\nmap_Kd does_not_exist.png\n");
+        let mtl_name = Path::new(&mtl).file_name().unwrap().to_string_lossy().into_owned();
+        let obj = fixture("texture", "obj", &format!("mtllib {}\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl paint\nf 1 2 3\n", mtl_name));
+        let error = extract_triangles(&obj, &Placement::new([0.0; 3], [1.0; 3]), None, false, &mut TextureCache::new(), |_| {}).err().unwrap();
+        fs::remove_file(&obj).unwrap();
+        fs::remove_file(&mtl).unwrap();
+        assert_eq!(error.line, 1);
+        assert!(error.message.starts_with(&format!("{}:2: texture ", mtl)), "{}", error);
+        assert!(error.message.contains("does_not_exist.png"), "{}", error);
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::fs;
+use super::objects;
+use super::objmanager::{self, MeshError, Placement};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+struct Property {
+    name: String,
+    kind: String,
+    //type of the length prefix for list properties like vertex_indices
+    list_count: Option<String>,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+//walks the data after end_header, either as whitespace separated text or packed binary
+struct Body<'a> {
+    format: Format,
+    bytes: &'a [u8],
+    position: usize,
+    tokens: std::str::SplitAsciiWhitespace<'a>,
+}
+
+impl<'a> Body<'a> {
+    fn new(format: Format, bytes: &'a [u8]) -> Result<Body<'a>, String> {
+        let text = if format == Format::Ascii {
+            std::str::from_utf8(bytes).map_err(|_| "ascii body is not valid text")?
+        } else {
+            ""
+        };
+        Ok(Body {
+            format,
+            bytes,
+            position: 0,
+            tokens: text.split_ascii_whitespace(),
+        })
+    }
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let end = self.position + N;
+        if end > self.bytes.len() {
+            return Err("unexpected end of file".to_string());
+        }
+        let mut bytes: [u8; N] = self.bytes[self.position..end].try_into().unwrap();
+        self.position = end;
+        if self.format == Format::BinaryBigEndian {
+            bytes.reverse();
+        }
+        Ok(bytes)
+    }
+    fn read(&mut self, kind: &str) -> Result<f64, String> {
+        if self.format == Format::Ascii {
+            let token = self.tokens.next().ok_or("unexpected end of file")?;
+            return token.parse().map_err(|_| format!("invalid number '{}'", token));
+        }
+        //binary data was reversed into little endian by take
+        Ok(match kind {
+            "char" | "int8" => i8::from_le_bytes(self.take()?) as f64,
+            "uchar" | "uint8" => u8::from_le_bytes(self.take()?) as f64,
+            "short" | "int16" => i16::from_le_bytes(self.take()?) as f64,
+            "ushort" | "uint16" => u16::from_le_bytes(self.take()?) as f64,
+            "int" | "int32" => i32::from_le_bytes(self.take()?) as f64,
+            "uint" | "uint32" => u32::from_le_bytes(self.take()?) as f64,
+            "float" | "float32" => f32::from_le_bytes(self.take()?) as f64,
+            "double" | "float64" => f64::from_le_bytes(self.take()?),
+            _ => return Err(format!("unknown property type '{}'", kind)),
+        })
+    }
+}
+
+//integer colours are stored 0..255, float ones 0..1
+fn color_scale(kind: &str) -> f32 {
+    match kind {
+        "uchar" | "uint8" | "char" | "int8" => 1.0 / 255.0,
+        "ushort" | "uint16" | "short" | "int16" => 1.0 / 65535.0,
+        _ => 1.0,
+    }
+}
+
+fn parse_header(filename: &str, bytes: &[u8]) -> Result<(Format, Vec<Element>, usize), MeshError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    let mut position = 0;
+    let mut line_number = 0;
+    loop {
+        line_number += 1;
+        let end = bytes[position..].iter().position(|&byte| byte == b'\n')
+            .ok_or_else(|| MeshError::new(filename, line_number, "header has no end_header".to_string()))?;
+        let line = String::from_utf8_lossy(&bytes[position..position + end]).to_string();
+        position += end + 1;
+
+        let error = |message: String| MeshError::new(filename, line_number, message);
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("ply") if line_number == 1 => {}
+            _ if line_number == 1 => return Err(error("not a ply file".to_string())),
+            Some("format") => {
+                format = Some(match words.next() {
+                    Some("ascii") => Format::Ascii,
+                    Some("binary_little_endian") => Format::BinaryLittleEndian,
+                    Some("binary_big_endian") => Format::BinaryBigEndian,
+                    other => return Err(error(format!("unknown format {:?}", other))),
+                });
+            }
+            Some("element") => {
+                let name = words.next().ok_or_else(|| error("element has no name".to_string()))?;
+                let count = words.next().and_then(|count| count.parse().ok())
+                    .ok_or_else(|| error("element has no count".to_string()))?;
+                elements.push(Element {
+                    name: name.to_string(),
+                    count,
+                    properties: vec![],
+                });
+            }
+            Some("property") => {
+                let element = elements.last_mut().ok_or_else(|| error("property before any element".to_string()))?;
+                let words: Vec<&str> = words.collect();
+                let property = match words.as_slice() {
+                    ["list", count, kind, name] => Property {
+                        name: name.to_string(),
+                        kind: kind.to_string(),
+                        list_count: Some(count.to_string()),
+                    },
+                    [kind, name] => Property {
+                        name: name.to_string(),
+                        kind: kind.to_string(),
+                        list_count: None,
+                    },
+                    _ => return Err(error("malformed property".to_string())),
+                };
+                element.properties.push(property);
+            }
+            Some("end_header") => break,
+            _ => {}
+        }
+    }
+    let format = format.ok_or_else(|| MeshError::new(filename, 0, "header has no format".to_string()))?;
+    Ok((format, elements, position))
+}
+
+//read from .ply file, ascii or binary, keeping per vertex normals, colours and uvs when present
+pub fn extract_triangles(filename: &str, placement: &Placement, material: objects::Material) -> Result<Vec<objects::Triangle>, MeshError> {
+    let bytes = fs::read(filename).map_err(|error| MeshError::new(filename, 0, error.to_string()))?;
+    let (format, elements, header_length) = parse_header(filename, &bytes)?;
+    let mut body = Body::new(format, &bytes[header_length..]).map_err(|message| MeshError::new(filename, 0, message))?;
+
+    let mut vertices = vec![];
+    let mut normals = vec![];
+    let mut colors = vec![];
+    let mut uvs = vec![];
+    let mut faces: Vec<Vec<usize>> = vec![];
+
+    for element in &elements {
+        let error = |index: usize, message: String| MeshError::new(filename, 0, format!("{} {}: {}", element.name, index, message));
+        for index in 0..element.count {
+            let mut values = HashMap::new();
+            let mut list = vec![];
+            for property in &element.properties {
+                match &property.list_count {
+                    Some(count_kind) => {
+                        let count = body.read(count_kind).map_err(|message| error(index, message))? as usize;
+                        //other lists, like the texcoord meshlab writes on faces, are read past
+                        let is_indices = property.name == "vertex_indices" || property.name == "vertex_index";
+                        for _ in 0..count {
+                            let value = body.read(&property.kind).map_err(|message| error(index, message))?;
+                            if !is_indices {
+                                continue;
+                            }
+                            if value < 0.0 {
+                                return Err(error(index, format!("negative index {}", value)));
+                            }
+                            list.push(value as usize);
+                        }
+                    }
+                    None => {
+                        let value = body.read(&property.kind).map_err(|message| error(index, message))?;
+                        values.insert(property.name.as_str(), (value as f32, property.kind.as_str()));
+                    }
+                }
+            }
+            match element.name.as_str() {
+                "vertex" => {
+                    let get = |name: &str| values.get(name).map(|&(value, _)| value);
+                    vertices.push([
+                        get("x").ok_or_else(|| error(index, "vertex has no x".to_string()))?,
+                        get("y").ok_or_else(|| error(index, "vertex has no y".to_string()))?,
+                        get("z").ok_or_else(|| error(index, "vertex has no z".to_string()))?,
+                    ]);
+                    if let (Some(x), Some(y), Some(z)) = (get("nx"), get("ny"), get("nz")) {
+                        normals.push([x, y, z]);
+                    }
+                    if let (Some(&(r, kind)), Some(&(g, _)), Some(&(b, _))) = (values.get("red"), values.get("green"), values.get("blue")) {
+                        let scale = color_scale(kind);
+                        colors.push([r * scale, g * scale, b * scale]);
+                    }
+                    let u = get("u").or(get("s")).or(get("texture_u"));
+                    let v = get("v").or(get("t")).or(get("texture_v"));
+                    if let (Some(u), Some(v)) = (u, v) {
+                        uvs.push([u, v]);
+                    }
+                }
+                "face" => {
+                    if list.len() < 3 {
+                        return Err(error(index, format!("face needs at least 3 vertices, found {}", list.len())));
+                    }
+                    if let Some(&outside) = list.iter().find(|&&vertex| vertex >= vertices.len()) {
+                        return Err(error(index, format!("index {} out of range, {} defined", outside, vertices.len())));
+                    }
+                    faces.push(list);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    //attributes are only used when every vertex has them
+    let has_normals = normals.len() == vertices.len();
+    let has_colors = colors.len() == vertices.len();
+    let has_uvs = uvs.len() == vertices.len();
+
+    let mut triangles = vec![];
+    for face in &faces {
+        let points: Vec<[f32; 3]> = face.iter().map(|&index| vertices[index]).collect();
+        for corners in objmanager::triangulate(&points) {
+            let indices = corners.map(|corner| face[corner]);
+            let face_uvs = if has_uvs { indices.map(|index| uvs[index]) } else { [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]] };
+            let mut triangle = objects::Triangle::new(indices.map(|index| vertices[index]), face_uvs, material.clone());
+            if has_normals {
+                triangle.vertex_normals = Some(indices.map(|index| normals[index]));
+            }
+            if has_colors {
+                triangle.vertex_colors = Some(indices.map(|index| colors[index]));
+            }
+            triangles.push(triangle);
+        }
+    }
+    Ok(placement.apply(triangles))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //writes a ply under the temp directory, named after the test so tests running at once don't share it
+    fn fixture(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("plymanager_{}_{}.ply", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    const HEADER: &str = "ply
+format ascii 1.0
+element vertex 4
+property float x
+property float y
+property float z
+element face 2
+property list uchar int vertex_indices
+property list uchar float texcoord
+end_header
+0 0 0
+1 0 0
+1 1 0
+0 1 0
+";
+
+    #[test]
+    fn face_texcoords_are_not_read_as_indices() {
+        let filename = fixture("texcoord", &format!("{}3 0 1 2 6 0 0 1 0 1 1\n3 0 2 3 6 0 0 1 1 0 1\n", HEADER));
+        let triangles = extract_triangles(&filename, &Placement::new([0.0; 3], [1.0; 3]), objects::Material::new([1.0; 3], 0.0, 0.0)).unwrap();
+        fs::remove_file(&filename).unwrap();
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(triangles[0].vertices, [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]]);
+        assert_eq!(triangles[1].vertices, [[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]]);
+    }
+
+    #[test]
+    fn negative_indices_are_an_error() {
+        let filename = fixture("negative", &format!("{}3 0 -1 2 0\n3 0 2 3 0\n", HEADER));
+        let result = extract_triangles(&filename, &Placement::new([0.0; 3], [1.0; 3]), objects::Material::new([1.0; 3], 0.0, 0.0));
+        fs::remove_file(&filename).unwrap();
+        let error = result.err().expect("a negative index should not load");
+        assert!(error.message.contains("negative index"), "{}", error);
+    }
+}
@@ -1,5 +1,4 @@
 use std::fs::File;
-use png;
 
 pub fn create_unused_filename() -> String {
     let mut i = 0;
@@ -12,6 +11,7 @@ pub fn create_unused_filename() -> String {
     }
 }
 
+#[derive(Clone)]
 pub struct Image {
     pub width: u32,
     pub height: u32,
@@ -37,6 +37,7 @@ impl Image {
         self.data[index * 4 + 3] = color[3];
     }
 
+    #[allow(dead_code)]
     pub fn get_pixel(&self, x: u32, y: u32) -> [u8; 4] {
         let index = (x + y * self.width) as usize;
         [
@@ -51,7 +52,16 @@ impl Image {
     }
     pub fn save_image(&self) {
         let file = File::create(&self.filename).unwrap();
-        let ref mut w = std::io::BufWriter::new(file);
+        let w = &mut std::io::BufWriter::new(file);
+        self.write_png(w);
+    }
+    //the png file's bytes without saving it anywhere
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        self.write_png(&mut bytes);
+        bytes
+    }
+    fn write_png<W: std::io::Write>(&self, w: W) {
         let mut encoder = png::Encoder::new(w, self.width, self.height);
         encoder.set_color(png::ColorType::Rgba);
         encoder.set_depth(png::BitDepth::Eight);
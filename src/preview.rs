@@ -0,0 +1,169 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json::json;
+use super::framebuffer::Tile;
+use super::png_manager::Image;
+
+//how often the page fetches the image and stats again
+const REFRESH_SECONDS: u32 = 2;
+//the png is encoded at most this often however many pages are watching
+const PNG_INTERVAL: Duration = Duration::from_secs(1);
+//a browser that opens a connection and never finishes its request is dropped after this long
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>Render preview</title></head>
+<body style="background: #202020; color: #e0e0e0; font-family: monospace">
+<img id="image" src="/image.png" style="max-width: 100%">
+<pre id="stats"></pre>
+<script>
+function refresh() {
+    document.getElementById("image").src = "/image.png?" + Date.now();
+    fetch("/stats.json").then(response => response.json()).then(stats => {
+        document.getElementById("stats").textContent = JSON.stringify(stats, null, 2);
+    });
+}
+refresh();
+setInterval(refresh, REFRESH * 1000);
+</script>
+</body>
+</html>
+"#;
+
+struct State {
+    image: Image,
+    png: Vec<u8>,
+    encoded: Option<Instant>,
+    frame: usize,
+    chunks_done: usize,
+    chunks_total: usize,
+    samples_per_pixel: usize,
+    rays: u64,
+    frame_start: Instant,
+    render_start: Instant,
+}
+
+//a small http server for watching a render from another machine. the root is a page that keeps itself up to date,
+//the image so far is at /image.png and how far along the render is at /stats.json
+pub struct Preview {
+    state: Mutex<State>,
+}
+
+impl Preview {
+    pub fn start(address: &str, width: usize, height: usize, samples_per_pixel: usize) -> Arc<Preview> {
+        let listener = TcpListener::bind(address).unwrap_or_else(|error| panic!("Could not listen on {}: {}", address, error));
+        println!("Live preview on http://{}", address);
+        //opaque black where nothing has rendered yet
+        let mut image = Image::new(width as u32, height as u32);
+        image.data.chunks_exact_mut(4).for_each(|pixel| pixel[3] = 255);
+        let preview = Arc::new(Preview {
+            state: Mutex::new(State {
+                image,
+                png: vec![],
+                encoded: None,
+                frame: 0,
+                chunks_done: 0,
+                chunks_total: 0,
+                samples_per_pixel,
+                rays: 0,
+                frame_start: Instant::now(),
+                render_start: Instant::now(),
+            }),
+        });
+        let serving = preview.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let preview = serving.clone();
+                thread::spawn(move || {
+                    let _ = preview.respond(stream);
+                });
+            }
+        });
+        preview
+    }
+
+    //the image is left as it is, so the last frame shows until the new one covers it
+    pub fn begin_frame(&self, frame: usize, chunks_total: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.frame = frame;
+        state.chunks_done = 0;
+        state.chunks_total = chunks_total;
+        state.rays = 0;
+        state.frame_start = Instant::now();
+    }
+
+    //copies the pixels a finished tile reached from the image being rendered
+    pub fn update(&self, image: &Image, tile: &Tile) {
+        let mut state = self.state.lock().unwrap();
+        let width = image.width as i64;
+        let (start_x, end_x) = (tile.x.max(0), (tile.x + tile.width as i64).min(width));
+        for y in tile.y.max(0)..(tile.y + tile.height as i64).min(image.height as i64) {
+            let row = (y * width * 4) as usize;
+            let range = row + start_x as usize * 4..row + end_x as usize * 4;
+            state.image.data[range.clone()].copy_from_slice(&image.data[range]);
+        }
+        state.chunks_done += 1;
+        state.rays += tile.rays;
+    }
+
+    //the pixels are copied under the lock and encoded outside it, so finished tiles aren't held up by the encoding.
+    //requests that come while an encode is going get the last png
+    fn png(&self) -> Vec<u8> {
+        let image = {
+            let mut state = self.state.lock().unwrap();
+            if !state.png.is_empty() && state.encoded.is_some_and(|encoded| encoded.elapsed() < PNG_INTERVAL) {
+                return state.png.clone();
+            }
+            state.encoded = Some(Instant::now());
+            state.image.clone()
+        };
+        let png = image.encode();
+        self.state.lock().unwrap().png = png.clone();
+        png
+    }
+
+    fn stats(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let elapsed = state.frame_start.elapsed().as_secs_f64();
+        let progress = if state.chunks_total == 0 { 0.0 } else { state.chunks_done as f64 / state.chunks_total as f64 };
+        let eta = if state.chunks_done == 0 { None } else { Some(elapsed * (state.chunks_total - state.chunks_done) as f64 / state.chunks_done as f64) };
+        json!({
+            "frame": state.frame,
+            "progress": progress,
+            "chunks_done": state.chunks_done,
+            "chunks_total": state.chunks_total,
+            "samples_per_pixel": state.samples_per_pixel,
+            "elapsed_seconds": elapsed,
+            "eta_seconds": eta,
+            "rays_per_second": if elapsed > 0.0 { state.rays as f64 / elapsed } else { 0.0 },
+            "total_elapsed_seconds": state.render_start.elapsed().as_secs_f64(),
+        }).to_string()
+    }
+
+    fn respond(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        let mut reader = BufReader::new(&stream);
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+        //the headers don't change anything, they just need reading past
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+            header.clear();
+        }
+        let target = request.split_whitespace().nth(1).unwrap_or("/");
+        let path = target.split('?').next().unwrap_or("/");
+        let (status, content_type, body) = match path {
+            "/" => ("200 OK", "text/html", PAGE.replace("REFRESH", &REFRESH_SECONDS.to_string()).into_bytes()),
+            "/image.png" => ("200 OK", "image/png", self.png()),
+            "/stats.json" => ("200 OK", "application/json", self.stats().into_bytes()),
+            _ => ("404 Not Found", "text/plain", b"Not found".to_vec()),
+        };
+        write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n", status, content_type, body.len())?;
+        stream.write_all(&body)?;
+        stream.flush()
+    }
+}
@@ -0,0 +1,646 @@
+use std::f32::consts::PI;
+use rand::prelude::*;
+use super::bvh::Aabb;
+use super::objects::{Hit, Hittable, Material, Ray, SurfaceSample, cross_product, dot_product, normalize, subtract};
+
+//rigid placement of a shape that is defined around its own origin
+#[derive(Clone, Copy)]
+pub struct Transform {
+    pub position: [f32; 3],
+    //columns are the shape's x, y and z axes in world space
+    pub axes: [[f32; 3]; 3],
+}
+
+impl Transform {
+    //rotation is in degrees, applied around x, then y, then z
+    pub fn new(position: [f32; 3], rotation: [f32; 3]) -> Transform {
+        let [x, y, z] = rotation.map(|angle| angle.to_radians());
+        let rotate = |vector: [f32; 3]| {
+            let vector = [vector[0], vector[1] * x.cos() - vector[2] * x.sin(), vector[1] * x.sin() + vector[2] * x.cos()];
+            let vector = [vector[0] * y.cos() + vector[2] * y.sin(), vector[1], -vector[0] * y.sin() + vector[2] * y.cos()];
+            [vector[0] * z.cos() - vector[1] * z.sin(), vector[0] * z.sin() + vector[1] * z.cos(), vector[2]]
+        };
+        Transform {
+            position,
+            axes: [rotate([1.0, 0.0, 0.0]), rotate([0.0, 1.0, 0.0]), rotate([0.0, 0.0, 1.0])],
+        }
+    }
+    pub fn local_ray(&self, ray: &Ray) -> ([f32; 3], [f32; 3]) {
+        let offset = subtract(ray.origin, self.position);
+        let origin = self.axes.map(|axis| dot_product(offset, axis));
+        let direction = self.axes.map(|axis| dot_product(ray.direction, axis));
+        (origin, direction)
+    }
+    pub fn direction_to_world(&self, direction: [f32; 3]) -> [f32; 3] {
+        let mut world = [0.0; 3];
+        for (i, value) in world.iter_mut().enumerate() {
+            *value = self.axes[0][i] * direction[0] + self.axes[1][i] * direction[1] + self.axes[2][i] * direction[2];
+        }
+        world
+    }
+    pub fn point_to_world(&self, point: [f32; 3]) -> [f32; 3] {
+        let direction = self.direction_to_world(point);
+        [direction[0] + self.position[0], direction[1] + self.position[1], direction[2] + self.position[2]]
+    }
+    pub fn point_to_local(&self, point: [f32; 3]) -> [f32; 3] {
+        let offset = subtract(point, self.position);
+        self.axes.map(|axis| dot_product(offset, axis))
+    }
+    //world box around a local box of the given half size centered on the origin
+    pub fn bounds(&self, half_size: [f32; 3]) -> Aabb {
+        self.bounds_around(&Aabb::new(half_size.map(|half| -half), half_size))
+    }
+    //world box around any box in the shape's own space
+    pub fn bounds_around(&self, local: &Aabb) -> Aabb {
+        let mut corners = vec![];
+        for x in [local.min[0], local.max[0]] {
+            for y in [local.min[1], local.max[1]] {
+                for z in [local.min[2], local.max[2]] {
+                    corners.push(self.point_to_world([x, y, z]));
+                }
+            }
+        }
+        Aabb::from_points(&corners)
+    }
+    //moves a point sampled on a shape of the given area into world space
+    fn sample(&self, point: [f32; 3], normal: [f32; 3], area: f32) -> SurfaceSample {
+        SurfaceSample {
+            point: self.point_to_world(point),
+            normal: normalize(self.direction_to_world(normal)),
+            pdf: 1.0 / area,
+        }
+    }
+}
+
+fn at(origin: [f32; 3], direction: [f32; 3], t: f32) -> [f32; 3] {
+    [origin[0] + direction[0] * t, origin[1] + direction[1] * t, origin[2] + direction[2] * t]
+}
+
+//turns a local hit into a world hit with the normal facing against the ray
+pub fn finish(transform: &Transform, material: &Material, ray: &Ray, t: f32, local_point: [f32; 3], local_normal: [f32; 3], uv: [f32; 2]) -> Hit {
+    let normal = normalize(transform.direction_to_world(local_normal));
+    let front_face = dot_product(ray.direction, normal) < 0.0;
+    let normal = if front_face { normal } else { [-normal[0], -normal[1], -normal[2]] };
+    material.shade(t, transform.point_to_world(local_point), normal, front_face, uv)
+}
+
+fn angle_uv(x: f32, z: f32) -> f32 {
+    0.5 + z.atan2(x) / (2.0 * PI)
+}
+
+//both roots of a t^2 + b t + c, nearest first
+fn quadratic_roots(a: f32, b: f32, c: f32) -> Option<(f32, f32)> {
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 || a.abs() < 1e-12 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    let near = (-b - root) / (2.0 * a);
+    let far = (-b + root) / (2.0 * a);
+    Some((near.min(far), near.max(far)))
+}
+
+#[derive(Clone)]
+pub struct Cuboid {
+    pub transform: Transform,
+    pub half_size: [f32; 3],
+    pub material: Material,
+}
+impl Cuboid {
+    pub fn new(transform: Transform, size: [f32; 3], material: Material) -> Cuboid {
+        Cuboid {
+            transform,
+            half_size: [size[0] / 2.0, size[1] / 2.0, size[2] / 2.0],
+            material,
+        }
+    }
+}
+impl Hittable for Cuboid {
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+        let (origin, direction) = self.transform.local_ray(ray);
+        //slab test
+        let mut near = f32::NEG_INFINITY;
+        let mut far = f32::INFINITY;
+        for i in 0..3 {
+            if direction[i].abs() < 1e-12 {
+                if origin[i].abs() > self.half_size[i] {
+                    return None;
+                }
+                continue;
+            }
+            let t1 = (-self.half_size[i] - origin[i]) / direction[i];
+            let t2 = (self.half_size[i] - origin[i]) / direction[i];
+            near = near.max(t1.min(t2));
+            far = far.min(t1.max(t2));
+        }
+        if near > far || far <= t_min {
+            return None;
+        }
+        let t = if near > t_min { near } else { far };
+        if t >= t_max {
+            return None;
+        }
+        let point = at(origin, direction, t);
+
+        //the face is the axis where the point sits closest to the side
+        let axis = (0..3).max_by(|&a, &b| (point[a].abs() / self.half_size[a]).total_cmp(&(point[b].abs() / self.half_size[b]))).unwrap();
+        let mut normal = [0.0, 0.0, 0.0];
+        normal[axis] = point[axis].signum();
+        let (u_axis, v_axis) = match axis {
+            0 => (2, 1),
+            1 => (0, 2),
+            _ => (0, 1),
+        };
+        let uv = [
+            (point[u_axis] / self.half_size[u_axis] + 1.0) / 2.0,
+            (point[v_axis] / self.half_size[v_axis] + 1.0) / 2.0,
+        ];
+        Some(finish(&self.transform, &self.material, ray, t, point, normal, uv))
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.transform.bounds(self.half_size))
+    }
+    fn sample_surface(&self, rng: &mut ThreadRng) -> Option<SurfaceSample> {
+        let [x, y, z] = self.half_size;
+        //pick a pair of opposite faces by their area, then a side and a point on it
+        let areas = [y * z, x * z, x * y];
+        let total = areas[0] + areas[1] + areas[2];
+        let pick = rng.gen::<f32>() * total;
+        let axis = if pick < areas[0] { 0 } else if pick < areas[0] + areas[1] { 1 } else { 2 };
+        let side = if rng.gen::<bool>() { 1.0 } else { -1.0 };
+        let mut point = self.half_size.map(|half| (rng.gen::<f32>() * 2.0 - 1.0) * half);
+        point[axis] = self.half_size[axis] * side;
+        let mut normal = [0.0, 0.0, 0.0];
+        normal[axis] = side;
+        //faces are 2 half sizes wide, and there are 2 of each
+        Some(self.transform.sample(point, normal, 8.0 * total))
+    }
+}
+
+//flat circle in the local xz plane facing +y
+#[derive(Clone)]
+pub struct Disk {
+    pub transform: Transform,
+    pub radius: f32,
+    pub material: Material,
+}
+impl Disk {
+    pub fn new(transform: Transform, radius: f32, material: Material) -> Disk {
+        Disk {
+            transform,
+            radius,
+            material,
+        }
+    }
+}
+impl Hittable for Disk {
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+        let (origin, direction) = self.transform.local_ray(ray);
+        if direction[1].abs() < 1e-12 {
+            return None;
+        }
+        let t = -origin[1] / direction[1];
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+        let point = at(origin, direction, t);
+        let distance = (point[0] * point[0] + point[2] * point[2]).sqrt();
+        if distance > self.radius {
+            return None;
+        }
+        let uv = [angle_uv(point[0], point[2]), distance / self.radius];
+        Some(finish(&self.transform, &self.material, ray, t, point, [0.0, 1.0, 0.0], uv))
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.transform.bounds([self.radius, 0.0, self.radius]))
+    }
+    fn sample_surface(&self, rng: &mut ThreadRng) -> Option<SurfaceSample> {
+        let distance = self.radius * rng.gen::<f32>().sqrt();
+        let angle = 2.0 * PI * rng.gen::<f32>();
+        let point = [distance * angle.cos(), 0.0, distance * angle.sin()];
+        Some(self.transform.sample(point, [0.0, 1.0, 0.0], PI * self.radius * self.radius))
+    }
+}
+
+//parallelogram spanned by two edges from a corner, handy as an area light
+#[derive(Clone)]
+pub struct Quad {
+    pub corner: [f32; 3],
+    pub edge_u: [f32; 3],
+    pub edge_v: [f32; 3],
+    pub normal: [f32; 3],
+    pub material: Material,
+}
+impl Quad {
+    pub fn new(corner: [f32; 3], edge_u: [f32; 3], edge_v: [f32; 3], material: Material) -> Quad {
+        Quad {
+            corner,
+            edge_u,
+            edge_v,
+            normal: normalize(cross_product(edge_u, edge_v)),
+            material,
+        }
+    }
+    //a width by depth rectangle in the transform's xz plane
+    pub fn rectangle(transform: Transform, size: [f32; 2], material: Material) -> Quad {
+        let corner = transform.point_to_world([-size[0] / 2.0, 0.0, -size[1] / 2.0]);
+        let edge_u = transform.direction_to_world([size[0], 0.0, 0.0]);
+        let edge_v = transform.direction_to_world([0.0, 0.0, size[1]]);
+        Quad {
+            corner,
+            edge_u,
+            edge_v,
+            //z cross x so the normal points along +y
+            normal: normalize(cross_product(edge_v, edge_u)),
+            material,
+        }
+    }
+}
+impl Hittable for Quad {
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+        let normal_dot_dir = dot_product(self.normal, ray.direction);
+        if normal_dot_dir.abs() < 1e-12 {
+            return None;
+        }
+        let t = dot_product(self.normal, subtract(self.corner, ray.origin)) / normal_dot_dir;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+        let point = at(ray.origin, ray.direction, t);
+        let offset = subtract(point, self.corner);
+        //solve offset = u * edge_u + v * edge_v in the plane
+        let uu = dot_product(self.edge_u, self.edge_u);
+        let uv = dot_product(self.edge_u, self.edge_v);
+        let vv = dot_product(self.edge_v, self.edge_v);
+        let ou = dot_product(offset, self.edge_u);
+        let ov = dot_product(offset, self.edge_v);
+        let determinant = uu * vv - uv * uv;
+        let u = (vv * ou - uv * ov) / determinant;
+        let v = (uu * ov - uv * ou) / determinant;
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return None;
+        }
+        let front_face = normal_dot_dir < 0.0;
+        let normal = if front_face { self.normal } else { [-self.normal[0], -self.normal[1], -self.normal[2]] };
+        Some(self.material.shade(t, point, normal, front_face, [u, v]))
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        let far = [self.corner[0] + self.edge_u[0] + self.edge_v[0], self.corner[1] + self.edge_u[1] + self.edge_v[1], self.corner[2] + self.edge_u[2] + self.edge_v[2]];
+        let u = [self.corner[0] + self.edge_u[0], self.corner[1] + self.edge_u[1], self.corner[2] + self.edge_u[2]];
+        let v = [self.corner[0] + self.edge_v[0], self.corner[1] + self.edge_v[1], self.corner[2] + self.edge_v[2]];
+        Some(Aabb::from_points(&[self.corner, u, v, far]))
+    }
+    fn sample_surface(&self, rng: &mut ThreadRng) -> Option<SurfaceSample> {
+        let (u, v) = (rng.gen::<f32>(), rng.gen::<f32>());
+        let cross = cross_product(self.edge_u, self.edge_v);
+        Some(SurfaceSample {
+            point: [self.corner[0] + self.edge_u[0] * u + self.edge_v[0] * v,
+                    self.corner[1] + self.edge_u[1] * u + self.edge_v[1] * v,
+                    self.corner[2] + self.edge_u[2] * u + self.edge_v[2] * v],
+            normal: self.normal,
+            pdf: 1.0 / dot_product(cross, cross).sqrt(),
+        })
+    }
+}
+
+//capped cylinder around the local y axis, centered on the origin
+#[derive(Clone)]
+pub struct Cylinder {
+    pub transform: Transform,
+    pub radius: f32,
+    pub height: f32,
+    pub material: Material,
+}
+impl Cylinder {
+    pub fn new(transform: Transform, radius: f32, height: f32, material: Material) -> Cylinder {
+        Cylinder {
+            transform,
+            radius,
+            height,
+            material,
+        }
+    }
+}
+impl Hittable for Cylinder {
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+        let (origin, direction) = self.transform.local_ray(ray);
+        let half = self.height / 2.0;
+        let mut best: Option<(f32, [f32; 3], [f32; 2])> = None;
+        let mut consider = |t: f32, normal: [f32; 3], uv: [f32; 2]| {
+            if t > t_min && t < t_max && best.is_none_or(|(closest, _, _)| t < closest) {
+                best = Some((t, normal, uv));
+            }
+        };
+
+        let a = direction[0] * direction[0] + direction[2] * direction[2];
+        let b = 2.0 * (origin[0] * direction[0] + origin[2] * direction[2]);
+        let c = origin[0] * origin[0] + origin[2] * origin[2] - self.radius * self.radius;
+        if let Some((near, far)) = quadratic_roots(a, b, c) {
+            for t in [near, far] {
+                let point = at(origin, direction, t);
+                if point[1].abs() <= half {
+                    consider(t, [point[0], 0.0, point[2]], [angle_uv(point[0], point[2]), (point[1] + half) / self.height]);
+                }
+            }
+        }
+        if direction[1].abs() > 1e-12 {
+            for side in [-1.0, 1.0] {
+                let t = (side * half - origin[1]) / direction[1];
+                let point = at(origin, direction, t);
+                let distance = (point[0] * point[0] + point[2] * point[2]).sqrt();
+                if distance <= self.radius {
+                    consider(t, [0.0, side, 0.0], [angle_uv(point[0], point[2]), distance / self.radius]);
+                }
+            }
+        }
+        best.map(|(t, normal, uv)| finish(&self.transform, &self.material, ray, t, at(origin, direction, t), normal, uv))
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.transform.bounds([self.radius, self.height / 2.0, self.radius]))
+    }
+    fn sample_surface(&self, rng: &mut ThreadRng) -> Option<SurfaceSample> {
+        let side_area = 2.0 * PI * self.radius * self.height;
+        let cap_area = PI * self.radius * self.radius;
+        let angle = 2.0 * PI * rng.gen::<f32>();
+        let (point, normal) = if rng.gen::<f32>() * (side_area + 2.0 * cap_area) < side_area {
+            let y = (rng.gen::<f32>() - 0.5) * self.height;
+            ([self.radius * angle.cos(), y, self.radius * angle.sin()], [angle.cos(), 0.0, angle.sin()])
+        } else {
+            let side = if rng.gen::<bool>() { 1.0 } else { -1.0 };
+            let distance = self.radius * rng.gen::<f32>().sqrt();
+            ([distance * angle.cos(), side * self.height / 2.0, distance * angle.sin()], [0.0, side, 0.0])
+        };
+        Some(self.transform.sample(point, normal, side_area + 2.0 * cap_area))
+    }
+}
+
+//cone around the local y axis with the apex at +height/2 and a capped base at -height/2
+#[derive(Clone)]
+pub struct Cone {
+    pub transform: Transform,
+    pub radius: f32,
+    pub height: f32,
+    pub material: Material,
+}
+impl Cone {
+    pub fn new(transform: Transform, radius: f32, height: f32, material: Material) -> Cone {
+        Cone {
+            transform,
+            radius,
+            height,
+            material,
+        }
+    }
+}
+impl Hittable for Cone {
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+        let (origin, direction) = self.transform.local_ray(ray);
+        let half = self.height / 2.0;
+        let slope = (self.radius / self.height).powi(2);
+        let mut best: Option<(f32, [f32; 3], [f32; 2])> = None;
+        let mut consider = |t: f32, normal: [f32; 3], uv: [f32; 2]| {
+            if t > t_min && t < t_max && best.is_none_or(|(closest, _, _)| t < closest) {
+                best = Some((t, normal, uv));
+            }
+        };
+
+        //x^2 + z^2 = slope * (half - y)^2
+        let apex_distance = half - origin[1];
+        let a = direction[0] * direction[0] + direction[2] * direction[2] - slope * direction[1] * direction[1];
+        let b = 2.0 * (origin[0] * direction[0] + origin[2] * direction[2] + slope * apex_distance * direction[1]);
+        let c = origin[0] * origin[0] + origin[2] * origin[2] - slope * apex_distance * apex_distance;
+        if let Some((near, far)) = quadratic_roots(a, b, c) {
+            for t in [near, far] {
+                let point = at(origin, direction, t);
+                if point[1] >= -half && point[1] <= half {
+                    let normal = [point[0], slope * (half - point[1]), point[2]];
+                    consider(t, normal, [angle_uv(point[0], point[2]), (point[1] + half) / self.height]);
+                }
+            }
+        }
+        if direction[1].abs() > 1e-12 {
+            let t = (-half - origin[1]) / direction[1];
+            let point = at(origin, direction, t);
+            let distance = (point[0] * point[0] + point[2] * point[2]).sqrt();
+            if distance <= self.radius {
+                consider(t, [0.0, -1.0, 0.0], [angle_uv(point[0], point[2]), distance / self.radius]);
+            }
+        }
+        best.map(|(t, normal, uv)| finish(&self.transform, &self.material, ray, t, at(origin, direction, t), normal, uv))
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.transform.bounds([self.radius, self.height / 2.0, self.radius]))
+    }
+    fn sample_surface(&self, rng: &mut ThreadRng) -> Option<SurfaceSample> {
+        let half = self.height / 2.0;
+        let side_area = PI * self.radius * (self.radius * self.radius + self.height * self.height).sqrt();
+        let base_area = PI * self.radius * self.radius;
+        let angle = 2.0 * PI * rng.gen::<f32>();
+        let (point, normal) = if rng.gen::<f32>() * (side_area + base_area) < side_area {
+            //the side gets wider linearly away from the apex, so its area grows with the square
+            let fraction = rng.gen::<f32>().sqrt();
+            let point = [self.radius * fraction * angle.cos(), half - self.height * fraction, self.radius * fraction * angle.sin()];
+            let slope = (self.radius / self.height).powi(2);
+            (point, [angle.cos(), slope * self.height / self.radius, angle.sin()])
+        } else {
+            let distance = self.radius * rng.gen::<f32>().sqrt();
+            ([distance * angle.cos(), -half, distance * angle.sin()], [0.0, -1.0, 0.0])
+        };
+        Some(self.transform.sample(point, normal, side_area + base_area))
+    }
+}
+
+//ring around the local y axis, major_radius to the middle of the tube and minor_radius for the tube itself
+#[derive(Clone)]
+pub struct Torus {
+    pub transform: Transform,
+    pub major_radius: f32,
+    pub minor_radius: f32,
+    pub material: Material,
+}
+impl Torus {
+    pub fn new(transform: Transform, major_radius: f32, minor_radius: f32, material: Material) -> Torus {
+        Torus {
+            transform,
+            major_radius,
+            minor_radius,
+            material,
+        }
+    }
+}
+impl Hittable for Torus {
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+        let (origin, direction) = self.transform.local_ray(ray);
+        let length = dot_product(direction, direction).sqrt();
+        let unit = direction.map(|value| (value / length) as f64);
+        let major = self.major_radius as f64;
+        let minor = self.minor_radius as f64;
+
+        //start from where the ray enters the bounding sphere to keep the quartic well conditioned
+        let bound = major + minor;
+        let o = origin.map(|value| value as f64);
+        let b = o[0] * unit[0] + o[1] * unit[1] + o[2] * unit[2];
+        let c = o[0] * o[0] + o[1] * o[1] + o[2] * o[2] - bound * bound;
+        let discriminant = b * b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let start = (-b - discriminant.sqrt()).max(0.0);
+        let o = [o[0] + unit[0] * start, o[1] + unit[1] * start, o[2] + unit[2] * start];
+
+        //(|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2) along the ray
+        let od = o[0] * unit[0] + o[1] * unit[1] + o[2] * unit[2];
+        let k = o[0] * o[0] + o[1] * o[1] + o[2] * o[2] + major * major - minor * minor;
+        let four_r2 = 4.0 * major * major;
+        let coefficients = [
+            k * k - four_r2 * (o[0] * o[0] + o[2] * o[2]),
+            4.0 * od * k - 2.0 * four_r2 * (o[0] * unit[0] + o[2] * unit[2]),
+            4.0 * od * od + 2.0 * k - four_r2 * (unit[0] * unit[0] + unit[2] * unit[2]),
+            4.0 * od,
+            1.0,
+        ];
+        let root = solve_quartic(coefficients).into_iter()
+            .map(|root| (root + start) / length as f64)
+            .filter(|&t| t > t_min as f64 && t < t_max as f64)
+            .fold(f64::INFINITY, f64::min);
+        if root == f64::INFINITY {
+            return None;
+        }
+        let t = root as f32;
+        let point = at(origin, direction, t);
+
+        let squared = dot_product(point, point);
+        let ring = self.major_radius * self.major_radius;
+        let tube = self.minor_radius * self.minor_radius;
+        let normal = [
+            point[0] * (squared - ring - tube),
+            point[1] * (squared + ring - tube),
+            point[2] * (squared - ring - tube),
+        ];
+        let ring_distance = (point[0] * point[0] + point[2] * point[2]).sqrt() - self.major_radius;
+        let uv = [angle_uv(point[0], point[2]), 0.5 + point[1].atan2(ring_distance) / (2.0 * PI)];
+        Some(finish(&self.transform, &self.material, ray, t, point, normal, uv))
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        let outer = self.major_radius + self.minor_radius;
+        Some(self.transform.bounds([outer, self.minor_radius, outer]))
+    }
+    fn sample_surface(&self, rng: &mut ThreadRng) -> Option<SurfaceSample> {
+        //the outside of the ring has more area than the inside, so reject tube angles in proportion
+        let tube_angle = loop {
+            let angle = 2.0 * PI * rng.gen::<f32>();
+            let distance = self.major_radius + self.minor_radius * angle.cos();
+            if rng.gen::<f32>() * (self.major_radius + self.minor_radius) <= distance {
+                break angle;
+            }
+        };
+        let ring_angle = 2.0 * PI * rng.gen::<f32>();
+        let normal = [tube_angle.cos() * ring_angle.cos(), tube_angle.sin(), tube_angle.cos() * ring_angle.sin()];
+        let distance = self.major_radius + self.minor_radius * tube_angle.cos();
+        let point = [distance * ring_angle.cos(), self.minor_radius * tube_angle.sin(), distance * ring_angle.sin()];
+        Some(self.transform.sample(point, normal, 4.0 * PI * PI * self.major_radius * self.minor_radius))
+    }
+}
+
+const EQUATION_EPSILON: f64 = 1e-9;
+
+fn is_zero(value: f64) -> bool {
+    value.abs() < EQUATION_EPSILON
+}
+
+//real roots of c[0] + c[1] x + c[2] x^2
+fn solve_quadratic(c: [f64; 3]) -> Vec<f64> {
+    let p = c[1] / (2.0 * c[2]);
+    let q = c[0] / c[2];
+    let discriminant = p * p - q;
+    if is_zero(discriminant) {
+        vec![-p]
+    } else if discriminant < 0.0 {
+        vec![]
+    } else {
+        let root = discriminant.sqrt();
+        vec![root - p, -root - p]
+    }
+}
+
+//real roots of c[0] + c[1] x + c[2] x^2 + c[3] x^3 using cardano's method
+fn solve_cubic(c: [f64; 4]) -> Vec<f64> {
+    let a = c[2] / c[3];
+    let b = c[1] / c[3];
+    let constant = c[0] / c[3];
+
+    let squared = a * a;
+    let p = (-squared / 3.0 + b) / 3.0;
+    let q = (2.0 / 27.0 * a * squared - a * b / 3.0 + constant) / 2.0;
+    let cubed = p * p * p;
+    let discriminant = q * q + cubed;
+
+    let roots = if is_zero(discriminant) {
+        if is_zero(q) {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if discriminant < 0.0 {
+        let phi = (-q / (-cubed).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        vec![t * phi.cos(), -t * (phi + std::f64::consts::PI / 3.0).cos(), -t * (phi - std::f64::consts::PI / 3.0).cos()]
+    } else {
+        let root = discriminant.sqrt();
+        vec![(root - q).cbrt() - (root + q).cbrt()]
+    };
+    roots.into_iter().map(|root| root - a / 3.0).collect()
+}
+
+//real roots of c[0] + c[1] x + ... + c[4] x^4 using ferrari's method, polished with newton steps
+fn solve_quartic(c: [f64; 5]) -> Vec<f64> {
+    let a = c[3] / c[4];
+    let b = c[2] / c[4];
+    let linear = c[1] / c[4];
+    let constant = c[0] / c[4];
+
+    //substitute x = y - a/4 to get y^4 + p y^2 + q y + r
+    let squared = a * a;
+    let p = -3.0 / 8.0 * squared + b;
+    let q = squared * a / 8.0 - a * b / 2.0 + linear;
+    let r = -3.0 / 256.0 * squared * squared + squared * b / 16.0 - a * linear / 4.0 + constant;
+
+    let mut roots = vec![];
+    if is_zero(r) {
+        roots = solve_cubic([q, p, 0.0, 1.0]);
+        roots.push(0.0);
+    } else {
+        let z = solve_cubic([r * p / 2.0 - q * q / 8.0, -r, -p / 2.0, 1.0])[0];
+        let mut u = z * z - r;
+        let mut v = 2.0 * z - p;
+        if is_zero(u) {
+            u = 0.0;
+        } else if u > 0.0 {
+            u = u.sqrt();
+        } else {
+            return roots;
+        }
+        if is_zero(v) {
+            v = 0.0;
+        } else if v > 0.0 {
+            v = v.sqrt();
+        } else {
+            return roots;
+        }
+        roots.extend(solve_quadratic([z - u, if q < 0.0 { -v } else { v }, 1.0]));
+        roots.extend(solve_quadratic([z + u, if q < 0.0 { v } else { -v }, 1.0]));
+    }
+
+    let evaluate = |x: f64| (((c[4] * x + c[3]) * x + c[2]) * x + c[1]) * x + c[0];
+    let derivative = |x: f64| ((4.0 * c[4] * x + 3.0 * c[3]) * x + 2.0 * c[2]) * x + c[1];
+    roots.into_iter().map(|root| root - a / 4.0).map(|mut root| {
+        for _ in 0..2 {
+            let slope = derivative(root);
+            if slope.abs() > 1e-12 {
+                root -= evaluate(root) / slope;
+            }
+        }
+        root
+    }).collect()
+}
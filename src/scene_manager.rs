@@ -1,15 +1,145 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs::File;
-use serde_json::{Result, Value};
-use super::objects::{Sphere, Ray, Hit, Triangle};
+use std::f32::consts::PI;
+use std::path::Path;
+use std::sync::Arc;
+use serde_json::Value;
+use super::objects::{Sphere, Ray, Hit, Triangle, Plane, Pattern, Material, Camera, Hittable, dot_product, normalize};
+use super::bvh::Bvh;
+use super::framebuffer::{Sample, Surface, Tile};
+use super::csg::{Csg, Operation};
+use super::sdf::{Sdf, SdfShape};
+use super::environment::{Environment, Sky};
+use super::lights::{self, Light};
+use super::volumes::{self, Density, Medium, MediumEvent, Volume};
+use super::voxelmanager::VoxelGrid;
+use super::animation;
+use super::motion::Moving;
+use super::primitives::{Transform, Cuboid, Disk, Quad, Cylinder, Cone, Torus};
+use super::texture_manager::{ColorSpace, TextureCache, WrapMode};
 use rand::prelude::*;
 
-use super::objmanager;
+use super::gltfmanager;
+use super::objmanager::{self, MeshError};
+use super::plymanager;
+use super::stlmanager;
+
+//what can go wrong reading a scene that isn't a mistake in the scene file's layout, those still panic
+pub enum SceneError {
+    Mesh(MeshError),
+    Keyframes(String),
+}
+
+impl From<MeshError> for SceneError {
+    fn from(error: MeshError) -> SceneError {
+        SceneError::Mesh(error)
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Mesh(error) => write!(f, "Error while reading mesh {}", error),
+            SceneError::Keyframes(message) => write!(f, "Error in keyframes: {}", message),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Scene {
-    pub spheres: Vec<Sphere>,
-    pub triangles: Vec<Triangle>,
+    pub camera: Camera,
+    //everything in the scene, rays are traced against bvh which is built from these
+    pub objects: Vec<Arc<dyn Hittable>>,
+    pub bvh: Arc<Bvh>,
+    pub environment: Environment,
+    pub lights: Vec<Light>,
+    //fog and smoke, their boundaries aren't in objects since rays pass straight through them
+    pub volumes: Vec<Volume>,
+    //where the camera is as the shutter closes, None when it stays still
+    pub shutter_camera: Option<Camera>,
+}
+//things that are slow to load and stay the same between frames unless their entry in the scene file changes,
+//meshes are kept by the text of the entry that loaded them
+pub struct SceneCache {
+    pub textures: TextureCache,
+    meshes: HashMap<String, Arc<dyn Hittable>>,
+    //lights and the camera that came out of gltf files alongside their meshes
+    gltf_extras: HashMap<String, (Vec<Light>, Option<Camera>)>,
+    //meshes the frame being loaded has asked for, the rest are dropped once it's done
+    used: HashSet<String>,
+}
+
+impl SceneCache {
+    pub fn new() -> SceneCache {
+        SceneCache {
+            textures: TextureCache::new(),
+            meshes: HashMap::new(),
+            gltf_extras: HashMap::new(),
+            used: HashSet::new(),
+        }
+    }
+    fn mesh(&mut self, entry: &Value) -> Result<Arc<dyn Hittable>, MeshError> {
+        let key = entry.to_string();
+        self.used.insert(key.clone());
+        if let Some(mesh) = self.meshes.get(&key) {
+            return Ok(mesh.clone());
+        }
+        let mesh = mesh_bvh(load_mesh(entry, &mut self.textures)?);
+        self.meshes.insert(key, mesh.clone());
+        Ok(mesh)
+    }
+    //lets go of meshes from earlier frames that have since moved
+    fn finish_frame(&mut self) {
+        let used = std::mem::take(&mut self.used);
+        self.meshes.retain(|key, _| used.contains(key));
+        self.gltf_extras.retain(|key, _| used.contains(key));
+    }
 }
+//kinds of scattering that have their own limit on how often a path can do them
+#[derive(Clone, Copy)]
+enum Bounce {
+    //volumes scattering light count as diffuse
+    Diffuse,
+    Specular,
+    Transmission,
+}
+
+//how long paths get, past min_bounces a path carries on with a chance set by how much light it can still carry
+//and is brightened to make up for the ones that stopped, so the limits only matter for paths that stay bright
+#[derive(Clone, Copy)]
+pub struct PathSettings {
+    pub min_bounces: usize,
+    pub max_diffuse: usize,
+    pub max_specular: usize,
+    pub max_transmission: usize,
+}
+
+impl PathSettings {
+    pub fn new(min_bounces: usize, max_diffuse: usize, max_specular: usize, max_transmission: usize) -> PathSettings {
+        PathSettings {
+            min_bounces,
+            max_diffuse,
+            max_specular,
+            max_transmission,
+        }
+    }
+    //counts the bounce that led to a new scattering point, false once there have been more of that kind than allowed,
+    //so light found straight after the last allowed bounce still gets in
+    fn allows(&self, counts: &mut [usize; 3], last_bounce: Option<Bounce>) -> bool {
+        let Some(bounce) = last_bounce else {
+            return true;
+        };
+        let limit = match bounce {
+            Bounce::Diffuse => self.max_diffuse,
+            Bounce::Specular => self.max_specular,
+            Bounce::Transmission => self.max_transmission,
+        };
+        counts[bounce as usize] += 1;
+        counts[bounce as usize] <= limit
+    }
+}
+
 //sd 1, mean 0
 fn gaussian_random(rng: &mut ThreadRng) -> f32 {
     let mut sum = 0.0;
@@ -18,6 +148,313 @@ fn gaussian_random(rng: &mut ThreadRng) -> f32 {
     }
     sum - 6.0
 }
+fn parse_vector(value: &Value) -> [f32; 3] {
+    [
+        value[0].as_f64().unwrap() as f32,
+        value[1].as_f64().unwrap() as f32,
+        value[2].as_f64().unwrap() as f32,
+    ]
+}
+//any material field left out of the scene entry keeps its default
+fn parse_material(entry: &Value, textures: &mut TextureCache) -> Result<Material, MeshError> {
+    let mut material = Material::new([1.0, 1.0, 1.0], 0.0, 0.0);
+    if !entry["color"].is_null() {
+        material.color = [
+            entry["color"][0].as_f64().unwrap() as f32,
+            entry["color"][1].as_f64().unwrap() as f32,
+            entry["color"][2].as_f64().unwrap() as f32,
+        ];
+    }
+    if let Some(light) = entry["light"].as_f64() {
+        material.light = light as f32;
+    }
+    if let Some(smoothness) = entry["smoothness"].as_f64() {
+        material.smoothness = smoothness as f32;
+    }
+    if let Some(metallic) = entry["metallic"].as_f64() {
+        material.metallic = metallic as f32;
+    }
+    if let Some(opacity) = entry["opacity"].as_f64() {
+        material.opacity = opacity as f32;
+    }
+    if let Some(ior) = entry["ior"].as_f64() {
+        material.ior = ior as f32;
+    }
+
+    let wrap = WrapMode::from_name(entry["texture_wrap"].as_str().unwrap_or("repeat"));
+    if let Some(filename) = entry["albedo_texture"].as_str() {
+        material.albedo_texture = Some(textures.load(filename, wrap, ColorSpace::Srgb).map_err(|error| MeshError::new(filename, 0, error))?);
+    }
+    if let Some(filename) = entry["emission_texture"].as_str() {
+        material.emission_texture = Some(textures.load(filename, wrap, ColorSpace::Srgb).map_err(|error| MeshError::new(filename, 0, error))?);
+    }
+    if let Some(filename) = entry["roughness_texture"].as_str() {
+        material.roughness_texture = Some(textures.load(filename, wrap, ColorSpace::Linear).map_err(|error| MeshError::new(filename, 0, error))?);
+    }
+    if let Some(filename) = entry["metallic_texture"].as_str() {
+        material.metallic_texture = Some(textures.load(filename, wrap, ColorSpace::Linear).map_err(|error| MeshError::new(filename, 0, error))?);
+    }
+    Ok(material)
+}
+//copies the fields the scene entry sets onto a material loaded from an .mtl or gltf file, so an entry with a color
+//paints the whole mesh and the Kd or base colors only show when it's left out
+fn override_material(entry: &Value, overrides: &Material, material: &mut Material) {
+    if !entry["color"].is_null() {
+        material.color = overrides.color;
+    }
+    if !entry["light"].is_null() {
+        material.light = overrides.light;
+        material.emission_color = None;
+    }
+    if !entry["smoothness"].is_null() {
+        material.smoothness = overrides.smoothness;
+    }
+    if !entry["metallic"].is_null() {
+        material.metallic = overrides.metallic;
+    }
+    if !entry["opacity"].is_null() {
+        material.opacity = overrides.opacity;
+    }
+    if !entry["ior"].is_null() {
+        material.ior = overrides.ior;
+    }
+    if !entry["albedo_texture"].is_null() {
+        material.albedo_texture = overrides.albedo_texture.clone();
+    }
+    if !entry["emission_texture"].is_null() {
+        material.emission_texture = overrides.emission_texture.clone();
+    }
+    if !entry["roughness_texture"].is_null() {
+        material.roughness_texture = overrides.roughness_texture.clone();
+        material.roughness_channel = overrides.roughness_channel;
+    }
+    if !entry["metallic_texture"].is_null() {
+        material.metallic_texture = overrides.metallic_texture.clone();
+        material.metallic_channel = overrides.metallic_channel;
+    }
+}
+fn parse_placement(obj: &Value) -> objmanager::Placement {
+    let translation = [
+        obj["position"][0].as_f64().unwrap() as f32,
+        obj["position"][1].as_f64().unwrap() as f32,
+        obj["position"][2].as_f64().unwrap() as f32,
+    ];
+    let scale = [
+        obj["scale"][0].as_f64().unwrap() as f32,
+        obj["scale"][1].as_f64().unwrap() as f32,
+        obj["scale"][2].as_f64().unwrap() as f32,
+    ];
+    let mut placement = objmanager::Placement::new(translation, scale);
+    if !obj["rotation"].is_null() {
+        placement.rotation = parse_vector(&obj["rotation"]);
+    }
+    placement.alignment = objmanager::Alignment::from_name(obj["align"].as_str().unwrap_or("none"));
+    placement.fit_size = obj["fit_size"].as_f64().map(|size| size as f32);
+    placement
+}
+//triangles of an obj, ply, stl or gltf file placed as the entry says, lights and cameras in gltf files are left out
+fn load_mesh(obj: &Value, textures: &mut TextureCache) -> Result<Vec<Triangle>, MeshError> {
+    let filename = obj["filename"].as_str().unwrap();
+    let placement = parse_placement(obj);
+    let groups = obj["groups"].as_array().map(|groups| {
+        groups.iter().map(|group| group.as_str().unwrap().to_string()).collect::<Vec<String>>()
+    });
+    let material = parse_material(obj, textures)?;
+    let extension = Path::new(filename).extension().and_then(|extension| extension.to_str()).unwrap_or("").to_lowercase();
+    match extension.as_str() {
+        "gltf" | "glb" => gltfmanager::extract_scene(filename, &placement, textures, |gltf_material| {
+            override_material(obj, &material, gltf_material);
+        }).map(|gltf| gltf.triangles),
+        "ply" => plymanager::extract_triangles(filename, &placement, material),
+        "stl" => {
+            let smooth_angle = obj["smooth_angle"].as_f64().unwrap_or(30.0) as f32;
+            stlmanager::extract_triangles(filename, &placement, material, smooth_angle)
+        }
+        _ => {
+            let mtl_smoothness = obj["mtl_smoothness"].as_bool().unwrap_or(false);
+            objmanager::extract_triangles(filename, &placement, groups.as_deref(), mtl_smoothness, textures, |mtl_material| {
+                override_material(obj, &material, mtl_material);
+            })
+        }
+    }
+}
+//triangles gathered into one object, which also gives the whole mesh one object id
+fn mesh_bvh(triangles: Vec<Triangle>) -> Arc<dyn Hittable> {
+    Arc::new(Bvh::new(triangles.into_iter().map(|triangle| Arc::new(triangle) as Arc<dyn Hittable>).collect()))
+}
+fn parse_camera(camera: &Value) -> Camera {
+    let position = parse_vector(&camera["position"]);
+    let target = if camera["look_at"].is_null() { [position[0], position[1], position[2] + 1.0] } else { parse_vector(&camera["look_at"]) };
+    let up = if camera["up"].is_null() { [0.0, 1.0, 0.0] } else { parse_vector(&camera["up"]) };
+    let mut parsed = Camera::look_at(position, target, up);
+    parsed.fov = camera["fov"].as_f64().map(|fov| (fov as f32).to_radians());
+    parsed
+}
+//an object built from entry, set moving if its position or rotation is different in the entry for shutter close
+fn with_motion(object: Arc<dyn Hittable>, entry: &Value, close: Option<&Value>, position_key: &str) -> Arc<dyn Hittable> {
+    let Some(close) = close else {
+        return object;
+    };
+    let placement = |entry: &Value| {
+        let vector = |key: &str| if entry[key].is_null() { [0.0; 3] } else { parse_vector(&entry[key]) };
+        (vector(position_key), vector("rotation"))
+    };
+    let (open, close) = (placement(entry), placement(close));
+    if open == close {
+        return object;
+    }
+    Arc::new(Moving::new(object, open, close))
+}
+pub fn read_scene_file(scene_name: &str) -> Value {
+    let file = File::open(scene_name).expect("File not found");
+    serde_json::from_reader(file).expect("Error while reading file")
+}
+//an entry of the shapes list, csg entries hold two more of these as left and right
+fn parse_shape(shape: &Value, cache: &mut SceneCache) -> Result<Arc<dyn Hittable>, MeshError> {
+    let kind = shape["type"].as_str().unwrap_or("");
+    if let Some(operation) = Operation::from_name(kind) {
+        let left = parse_shape(&shape["left"], cache)?;
+        let right = parse_shape(&shape["right"], cache)?;
+        return Ok(Arc::new(Csg::new(operation, left, right)));
+    }
+    if kind == "mesh" {
+        //kept in its own bvh so the mesh acts as one closed shape
+        return cache.mesh(shape);
+    }
+    let material = parse_material(shape, &mut cache.textures)?;
+    let position = if shape["position"].is_null() { [0.0, 0.0, 0.0] } else { parse_vector(&shape["position"]) };
+    let rotation = if shape["rotation"].is_null() { [0.0, 0.0, 0.0] } else { parse_vector(&shape["rotation"]) };
+    let transform = Transform::new(position, rotation);
+    let number = |key: &str, default: f64| shape[key].as_f64().unwrap_or(default) as f32;
+    Ok(match kind {
+        "sphere" => Arc::new(Sphere::new(position, number("radius", 1.0), material)),
+        "box" => Arc::new(Cuboid::new(transform, if shape["size"].is_null() { [1.0, 1.0, 1.0] } else { parse_vector(&shape["size"]) }, material)),
+        "disk" => Arc::new(Disk::new(transform, number("radius", 1.0), material)),
+        "rectangle" => {
+            let size = [shape["size"][0].as_f64().unwrap_or(1.0) as f32, shape["size"][1].as_f64().unwrap_or(1.0) as f32];
+            Arc::new(Quad::rectangle(transform, size, material))
+        }
+        "quad" => Arc::new(Quad::new(parse_vector(&shape["corner"]), parse_vector(&shape["edge_u"]), parse_vector(&shape["edge_v"]), material)),
+        "cylinder" => Arc::new(Cylinder::new(transform, number("radius", 1.0), number("height", 1.0), material)),
+        "cone" => Arc::new(Cone::new(transform, number("radius", 1.0), number("height", 1.0), material)),
+        "torus" => Arc::new(Torus::new(transform, number("major_radius", 1.0), number("minor_radius", 0.25), material)),
+        "sdf" => Arc::new(SdfShape::new(transform, parse_sdf(&shape["sdf"]), material)),
+        other => panic!("Unknown shape type {:?}", other),
+    })
+}
+//an entry of the lights list, area lights glow towards +y before rotating like the matching shapes
+fn parse_light(light: &Value) -> Light {
+    let number = |key: &str, default: f64| light[key].as_f64().unwrap_or(default) as f32;
+    let vector = |key: &str, default: [f32; 3]| if light[key].is_null() { default } else { parse_vector(&light[key]) };
+    let mut color = vector("color", [1.0, 1.0, 1.0]);
+    if let Some(kelvin) = light["temperature"].as_f64() {
+        let tint = lights::temperature_color(kelvin as f32);
+        color = [0, 1, 2].map(|i| color[i] * tint[i]);
+    }
+    let position = vector("position", [0.0, 0.0, 0.0]);
+    let transform = Transform::new(position, vector("rotation", [0.0, 0.0, 0.0]));
+    let power = number("power", 100.0);
+    //the emitter itself only needs to be hit, lights don't reflect anything
+    let material = Material::new([0.0, 0.0, 0.0], 0.0, 0.0);
+    match light["type"].as_str() {
+        Some("point") => Light::point(position, power, color),
+        Some("spot") => Light::spot(position, vector("direction", [0.0, -1.0, 0.0]), power, color, number("inner_angle", 20.0), number("outer_angle", 30.0)),
+        Some("directional") => Light::directional(vector("direction", [0.0, -1.0, 0.0]), number("irradiance", 1.0), color),
+        Some("rectangle") => {
+            let size = [light["size"][0].as_f64().unwrap_or(1.0) as f32, light["size"][1].as_f64().unwrap_or(1.0) as f32];
+            Light::area(Arc::new(Quad::rectangle(transform, size, material)), size[0] * size[1], power, color)
+        }
+        Some("disk") => {
+            let radius = number("radius", 0.5);
+            Light::area(Arc::new(Disk::new(transform, radius, material)), PI * radius * radius, power, color)
+        }
+        other => panic!("Unknown light type {:?}", other),
+    }
+}
+//absorption and scattering can be one number or a color
+fn parse_medium(entry: &Value) -> Medium {
+    let coefficient = |key: &str| match entry[key].as_f64() {
+        Some(value) => [value as f32; 3],
+        None if entry[key].is_null() => [0.0; 3],
+        None => parse_vector(&entry[key]),
+    };
+    Medium::new(coefficient("absorption"), coefficient("scattering"), entry["anisotropy"].as_f64().unwrap_or(0.0) as f32)
+}
+//a voxel grid fills a box placed like the shapes, anything else fills its shape and can be given a noise density
+fn parse_volume(entry: &Value, cache: &mut SceneCache) -> Result<Volume, MeshError> {
+    let medium = parse_medium(entry);
+    if let Some(filename) = entry["grid"].as_str() {
+        let position = if entry["position"].is_null() { [0.0, 0.0, 0.0] } else { parse_vector(&entry["position"]) };
+        let rotation = if entry["rotation"].is_null() { [0.0, 0.0, 0.0] } else { parse_vector(&entry["rotation"]) };
+        let size = if entry["size"].is_null() { [1.0, 1.0, 1.0] } else { parse_vector(&entry["size"]) };
+        let transform = Transform::new(position, rotation);
+        let boundary = Cuboid::new(transform, size, Material::new([0.0, 0.0, 0.0], 0.0, 0.0));
+        let mut volume = Volume::new(Arc::new(boundary), medium);
+        volume.density = Some(Density::Grid { transform, size, grid: Arc::new(VoxelGrid::load(filename)) });
+        return Ok(volume);
+    }
+    let mut volume = Volume::new(parse_shape(&entry["shape"], cache)?, medium);
+    let density = &entry["density"];
+    match density["type"].as_str() {
+        None => {}
+        Some("noise") => {
+            let number = |key: &str, default: f64| density[key].as_f64().unwrap_or(default) as f32;
+            volume.density = Some(Density::Noise {
+                frequency: number("frequency", 1.0),
+                octaves: density["octaves"].as_u64().unwrap_or(4) as usize,
+                contrast: number("contrast", 1.0),
+                bias: number("bias", 0.0),
+                seed: density["seed"].as_u64().unwrap_or(0) as u32,
+            });
+        }
+        Some(other) => panic!("Unknown density type {}", other),
+    }
+    Ok(volume)
+}
+//a node of an sdf expression tree, any node can be moved with position
+fn parse_sdf(node: &Value) -> Sdf {
+    let number = |key: &str, default: f64| node[key].as_f64().unwrap_or(default) as f32;
+    let child = |key: &str| Box::new(parse_sdf(&node[key]));
+    let sdf = match node["type"].as_str() {
+        Some("sphere") => Sdf::Sphere { radius: number("radius", 1.0) },
+        Some("box") => {
+            let size = if node["size"].is_null() { [1.0, 1.0, 1.0] } else { parse_vector(&node["size"]) };
+            Sdf::Box { half_size: size.map(|side| side / 2.0), rounding: number("rounding", 0.0) }
+        }
+        Some("torus") => Sdf::Torus { major_radius: number("major_radius", 1.0), minor_radius: number("minor_radius", 0.25) },
+        Some("mandelbulb") => Sdf::Mandelbulb { power: number("power", 8.0), iterations: node["iterations"].as_u64().unwrap_or(8) as usize },
+        Some("smooth_union") => Sdf::SmoothUnion {
+            smoothness: number("smoothness", 0.0),
+            children: node["children"].as_array().expect("smooth_union needs children").iter().map(parse_sdf).collect(),
+        },
+        Some("repeat") => Sdf::Repeat {
+            spacing: parse_vector(&node["spacing"]),
+            count: if node["count"].is_null() { None } else { Some(parse_vector(&node["count"])) },
+            child: child("child"),
+        },
+        //degrees per unit of height
+        Some("twist") => Sdf::twist(number("rate", 45.0).to_radians(), parse_sdf(&node["child"])),
+        other => panic!("Unknown sdf type {:?}", other),
+    };
+    if node["position"].is_null() {
+        return sdf;
+    }
+    Sdf::Translate { offset: parse_vector(&node["position"]), child: Box::new(sdf) }
+}
+//adds light to the emission, direct or indirect pass by how many times it scattered
+fn add_light(passes: &mut [[f32; 3]; 3], scatters: usize, light: [f32; 3]) {
+    let pass = &mut passes[scatters.min(2)];
+    *pass = [0, 1, 2].map(|i| pass[i] + light[i]);
+}
+//weight for one of two ways of sampling the same light, the one more likely to pick the direction gets more
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    if pdf <= 0.0 {
+        return 0.0;
+    }
+    //written as a ratio so a huge pdf from a light seen edge on doesn't overflow when squared
+    let ratio = other_pdf / pdf;
+    1.0 / (1.0 + ratio * ratio)
+}
 fn specular_reflection(ray: &mut Ray, closest_hit: &Hit) -> [f32; 3] {
     let dot = ray.direction[0] * closest_hit.normal[0] +
               ray.direction[1] * closest_hit.normal[1] +
@@ -29,53 +466,142 @@ fn specular_reflection(ray: &mut Ray, closest_hit: &Hit) -> [f32; 3] {
     ]
 }
 
+//refracts through a dielectric surface, reflecting instead on total internal reflection
+//or when the schlick fresnel term says so
+fn transmission(ray: &mut Ray, closest_hit: &Hit, rng: &mut ThreadRng) -> [f32; 3] {
+    let length = (ray.direction[0].powi(2) + ray.direction[1].powi(2) + ray.direction[2].powi(2)).sqrt();
+    let direction = [ray.direction[0] / length, ray.direction[1] / length, ray.direction[2] / length];
+    let normal = closest_hit.normal;
+    let eta = if closest_hit.front_face { 1.0 / closest_hit.ior } else { closest_hit.ior };
+
+    let cos_i = -(direction[0] * normal[0] + direction[1] * normal[1] + direction[2] * normal[2]).min(1.0);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+    let r0 = ((1.0 - closest_hit.ior) / (1.0 + closest_hit.ior)).powi(2);
+    let reflectance = r0 + (1.0 - r0) * (1.0 - cos_i).powi(5);
+    if sin2_t > 1.0 || rng.gen::<f32>() < reflectance {
+        return specular_reflection(ray, closest_hit);
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    [
+        eta * direction[0] + (eta * cos_i - cos_t) * normal[0],
+        eta * direction[1] + (eta * cos_i - cos_t) * normal[1],
+        eta * direction[2] + (eta * cos_i - cos_t) * normal[2],
+    ]
+}
+
 impl Scene {
-    pub fn new(scene_name: String) -> Scene {
+    //the scene with everything animated moved to where it is at frame, anything in the cache is reused.
+    //shutter is how many frames it stays open for, the camera and any spheres, shapes and objects whose placement
+    //changes in that time move while it's open, everything else stays where it was at frame. meshes that can't be
+    //read and keyframes that can't be followed are the only errors returned, anything else wrong with the scene file panics
+    pub fn at_frame(data: &Value, frame: f32, shutter: f32, cache: &mut SceneCache) -> Result<Scene, SceneError> {
+        let close = if shutter > 0.0 { Some(animation::resolve(data, frame + shutter).map_err(SceneError::Keyframes)?) } else { None };
+        let close = close.as_ref();
+        let data = animation::resolve(data, frame).map_err(SceneError::Keyframes)?;
         let mut scene = Scene {
-            spheres: Vec::new(),
-            triangles: Vec::new(),
+            camera: Camera::new([0.0, 0.0, 0.0]),
+            objects: Vec::new(),
+            bvh: Arc::new(Bvh::new(Vec::new())),
+            environment: Environment::Black,
+            lights: Vec::new(),
+            volumes: Vec::new(),
+            shutter_camera: None,
         };
-        let file = File::open(scene_name).expect("File not found");
-        let data: Value = serde_json::from_reader(file).expect("Error while reading file");
-        for sphere in data["spheres"].as_array().unwrap() {
+        let textures = &mut cache.textures;
+        if !data["camera"].is_null() {
+            scene.camera = parse_camera(&data["camera"]);
+            if let Some(close) = close.filter(|close| close["camera"] != data["camera"]) {
+                scene.shutter_camera = Some(parse_camera(&close["camera"]));
+            }
+        }
+        let environment = &data["environment"];
+        let intensity = environment["intensity"].as_f64().unwrap_or(1.0) as f32;
+        let scaled = |color: [f32; 3]| color.map(|value| value * intensity);
+        scene.environment = match environment["type"].as_str() {
+            None => Environment::Black,
+            Some("constant") => Environment::Constant { color: scaled(parse_vector(&environment["color"])) },
+            Some("gradient") => Environment::Gradient {
+                top: scaled(parse_vector(&environment["top"])),
+                bottom: scaled(parse_vector(&environment["bottom"])),
+            },
+            Some("map") => {
+                let filename = environment["filename"].as_str().expect("environment map needs a filename");
+                let texture = textures.load(filename, WrapMode::Repeat, ColorSpace::Srgb).map_err(|error| MeshError::new(filename, 0, error))?;
+                Environment::map(texture, environment["rotation"].as_f64().unwrap_or(0.0) as f32, intensity)
+            }
+            Some("sky") => {
+                let number = |key: &str, default: f64| environment[key].as_f64().unwrap_or(default) as f32;
+                Environment::Sky(Sky::new(
+                    number("sun_elevation", 45.0),
+                    number("sun_azimuth", 0.0),
+                    number("turbidity", 3.0),
+                    intensity,
+                    number("sun_size", 0.53),
+                    number("sun_intensity", 1.0),
+                ))
+            }
+            Some(other) => panic!("Unknown environment type {}", other),
+        };
+        for (index, sphere) in data["spheres"].as_array().unwrap().iter().enumerate() {
             let center = [
                 sphere["center"][0].as_f64().unwrap() as f32,
                 sphere["center"][1].as_f64().unwrap() as f32,
                 sphere["center"][2].as_f64().unwrap() as f32,
             ];
             let radius = sphere["radius"].as_f64().unwrap() as f32;
-            let color = [
-                sphere["color"][0].as_f64().unwrap() as f32,
-                sphere["color"][1].as_f64().unwrap() as f32,
-                sphere["color"][2].as_f64().unwrap() as f32,
-            ];
-            let light = sphere["light"].as_f64().unwrap() as f32;
-            let smoothness = sphere["smoothness"].as_f64().unwrap() as f32;
-            scene.spheres.push(Sphere::new(center, radius, color, light, smoothness));
+            let material = parse_material(sphere, textures)?;
+            let closed = close.map(|close| &close["spheres"][index]);
+            scene.objects.push(with_motion(Arc::new(Sphere::new(center, radius, material)), sphere, closed, "center"));
+        }
+        for plane in data["planes"].as_array().unwrap_or(&vec![]) {
+            let point = parse_vector(&plane["point"]);
+            let normal = if plane["normal"].is_null() { [0.0, 1.0, 0.0] } else { parse_vector(&plane["normal"]) };
+            let material = parse_material(plane, textures)?;
+            let pattern = &plane["pattern"];
+            let scale = pattern["scale"].as_f64().unwrap_or(1.0) as f32;
+            let color = if pattern["color"].is_null() { [0.0, 0.0, 0.0] } else { parse_vector(&pattern["color"]) };
+            let pattern = match pattern["type"].as_str() {
+                Some("checker") => Pattern::Checker { scale, color },
+                Some("grid") => Pattern::Grid { scale, line_width: pattern["line_width"].as_f64().unwrap_or(0.05) as f32, color },
+                _ => Pattern::None,
+            };
+            scene.add(Plane::new(point, normal, material, pattern));
+        }
+        for (index, shape) in data["shapes"].as_array().unwrap_or(&vec![]).iter().enumerate() {
+            let closed = close.map(|close| &close["shapes"][index]);
+            scene.objects.push(with_motion(parse_shape(shape, cache)?, shape, closed, "position"));
         }
-        for obj in data["objects"].as_array().unwrap() {
+        for light in data["lights"].as_array().unwrap_or(&vec![]) {
+            scene.lights.push(parse_light(light));
+        }
+        for volume in data["volumes"].as_array().unwrap_or(&vec![]) {
+            scene.volumes.push(parse_volume(volume, cache)?);
+        }
+        for (index, obj) in data["objects"].as_array().unwrap().iter().enumerate() {
+            let closed = close.map(|close| &close["objects"][index]);
             let filename = obj["filename"].as_str().unwrap();
-            let light = obj["light"].as_f64().unwrap() as f32;
-            let color = [
-                obj["color"][0].as_f64().unwrap() as f32,
-                obj["color"][1].as_f64().unwrap() as f32,
-                obj["color"][2].as_f64().unwrap() as f32,
-            ];
-            let translation = [
-                obj["position"][0].as_f64().unwrap() as f32,
-                obj["position"][1].as_f64().unwrap() as f32,
-                obj["position"][2].as_f64().unwrap() as f32,
-            ];
-            let scale = [
-                obj["scale"][0].as_f64().unwrap() as f32,
-                obj["scale"][1].as_f64().unwrap() as f32,
-                obj["scale"][2].as_f64().unwrap() as f32,
-            ];
-            let smoothness = obj["smoothness"].as_f64().unwrap() as f32;
-            let triangles = objmanager::extract_triangles(filename, translation, scale, light, color, smoothness);
-            for triangle in triangles {
-                scene.triangles.push(triangle);
+            let extension = Path::new(filename).extension().and_then(|extension| extension.to_str()).unwrap_or("").to_lowercase();
+            if extension == "gltf" || extension == "glb" {
+                let key = obj.to_string();
+                cache.used.insert(key.clone());
+                if !cache.meshes.contains_key(&key) {
+                    let material = parse_material(obj, &mut cache.textures)?;
+                    let gltf = gltfmanager::extract_scene(filename, &parse_placement(obj), &mut cache.textures, |gltf_material| {
+                        override_material(obj, &material, gltf_material);
+                    })?;
+                    cache.meshes.insert(key.clone(), mesh_bvh(gltf.triangles));
+                    cache.gltf_extras.insert(key.clone(), (gltf.lights, gltf.camera));
+                }
+                scene.objects.push(with_motion(cache.meshes[&key].clone(), obj, closed, "position"));
+                let (lights, camera) = &cache.gltf_extras[&key];
+                scene.lights.extend(lights.iter().cloned());
+                //a camera written in the scene file wins over the imported one
+                if let (Some(camera), true) = (camera, data["camera"].is_null()) {
+                    scene.camera = camera.clone();
+                }
+                continue;
             }
+            scene.objects.push(with_motion(cache.mesh(obj)?, obj, closed, "position"));
         }
         /* 
         println!("{}", scene.spheres[0].center[0]);
@@ -86,10 +612,76 @@ impl Scene {
         println!("{}", scene.spheres[0].color[1]);
         println!("{}", scene.spheres[0].color[2]);
         */
-        scene
+        //global fog fills a big sphere around the camera so rays that miss everything still see the sky beyond it,
+        //left until last since a gltf file can move the camera
+        let fog = &data["fog"];
+        if !fog.is_null() {
+            let extent = fog["extent"].as_f64().unwrap_or(100.0) as f32;
+            let boundary = Sphere::new(scene.camera.position, extent, Material::new([0.0, 0.0, 0.0], 0.0, 0.0));
+            scene.volumes.push(Volume::new(Arc::new(boundary), parse_medium(fog)));
+        }
+        cache.finish_frame();
+        scene.build();
+        Ok(scene)
+    }
+    pub fn add<T: Hittable + 'static>(&mut self, object: T) {
+        self.objects.push(Arc::new(object));
+    }
+    //objects added after this aren't seen by rays until it is called again
+    pub fn build(&mut self) {
+        self.bvh = Arc::new(Bvh::new(self.objects.clone()));
+    }
+    //how much light gets from distance along direction back to origin, None when something solid,
+    //including the body of an area light, is in the way
+    fn visibility(&self, origin: [f32; 3], direction: [f32; 3], distance: f32, time: f32) -> Option<[f32; 3]> {
+        let mut ray = Ray::new(origin, direction);
+        ray.time = time;
+        //stop just short so the light being aimed at doesn't block itself
+        let distance = distance * 0.9999;
+        if self.bvh.intersect(&ray, 0.00001, distance).is_some() || self.lights.iter().any(|light| light.intersect(&ray, 0.00001, distance).is_some()) {
+            return None;
+        }
+        if self.volumes.is_empty() {
+            return Some([1.0; 3]);
+        }
+        Some(volumes::transmittance(&self.volumes, &ray, distance, &mut rand::thread_rng()))
+    }
+    //light reaching point straight from the environment and every light, shared with the bounce by mis,
+    //scattering gives the fraction sent on towards the camera for a direction which is also the pdf of bouncing that way
+    //delta_only leaves out the environment and area lights, for bounces that can't share them by mis
+    fn direct_light(&self, point: [f32; 3], time: f32, rng: &mut ThreadRng, delta_only: bool, scattering: impl Fn([f32; 3]) -> Option<f32>) -> [f32; 3] {
+        let mut light = [0.0; 3];
+        if let Some(sample) = self.environment.sample(rng).filter(|_| !delta_only) {
+            if let Some(value) = scattering(sample.direction) {
+                if let Some(visibility) = self.visibility(point, sample.direction, f32::INFINITY, time) {
+                    let scale = value * power_heuristic(sample.pdf, value) / sample.pdf;
+                    light = [0, 1, 2].map(|i| light[i] + sample.radiance[i] * visibility[i] * scale);
+                }
+            }
+        }
+        for source in self.lights.iter().filter(|source| source.is_delta() || !delta_only) {
+            let Some(illumination) = source.sample(point, rng) else {
+                continue;
+            };
+            let Some(value) = scattering(illumination.direction) else {
+                continue;
+            };
+            if let Some(visibility) = self.visibility(point, illumination.direction, illumination.distance, time) {
+                //lights that are a single point or direction can't be found by bouncing so they keep all of theirs
+                let scale = match illumination.pdf {
+                    Some(pdf) => value * power_heuristic(pdf, value) / pdf,
+                    None => value,
+                };
+                light = [0, 1, 2].map(|i| light[i] + illumination.radiance[i] * visibility[i] * scale);
+            }
+        }
+        light
     }
-    pub fn trace(&self, x:usize, y:usize, bounces: usize, samples: usize, antialiasing: bool, width: usize, height: usize, fov: f32) -> [u8; 3] {
+    //every sample is splatted into the tile, which has to cover the pixel
+    #[allow(clippy::too_many_arguments)]
+    pub fn trace(&self, x:usize, y:usize, settings: &PathSettings, samples: usize, antialiasing: bool, width: usize, height: usize, fov: f32, tile: &mut Tile) {
         let mut rng = rand::thread_rng();
+        let fov = self.camera.fov.unwrap_or(fov);
         let mut antialiasing_x: f32 = 0.0;
         let mut antialiasing_y: f32 = 0.0;
 
@@ -105,16 +697,16 @@ impl Scene {
         let dir_y = -y_angle.sin();
         
         let length = (dir_x.powi(2) + dir_y.powi(2) + 1.0).sqrt();
-        let mut ray = Ray::new([0.0, 0.0, 0.0], [dir_x / length, dir_y / length, 1.0 / length]);
-
-        let mut colour_sum = [0.0, 0.0, 0.0];
-        let initial_origin = [ray.origin[0], ray.origin[1], ray.origin[2]];
-
-        
+        let mut ray = Ray::new(self.camera.position, self.camera.orient([dir_x / length, dir_y / length, 1.0 / length]));
+        //one towards the environment and one for each light every time direct light is gathered, the odd one is
+        //skipped when the light faces away
+        let shadow_rays = self.lights.len() as u64 + 1;
+        let delta_shadow_rays = self.lights.iter().filter(|light| light.is_delta()).count() as u64;
 
         for _ in 0..samples {
 
-            ray.origin = [initial_origin[0], initial_origin[1], initial_origin[2]];
+            ray.origin = self.camera.position;
+            ray.time = rng.gen::<f32>();
 
             if antialiasing {
                 antialiasing_x = rng.gen::<f32>() - 0.5;
@@ -126,43 +718,113 @@ impl Scene {
     
             let dir_x = x_angle.sin();
             let dir_y = -y_angle.sin();
-            ray.direction = [dir_x / length, dir_y / length, 1.0 / length];
+            ray.direction = self.camera.orient([dir_x / length, dir_y / length, 1.0 / length]);
+            if let Some(close) = &self.shutter_camera {
+                let towards = close.orient([dir_x / length, dir_y / length, 1.0 / length]);
+                ray.origin = [0, 1, 2].map(|i| ray.origin[i] + (close.position[i] - ray.origin[i]) * ray.time);
+                ray.direction = normalize([0, 1, 2].map(|i| ray.direction[i] + (towards[i] - ray.direction[i]) * ray.time));
+            }
             
             ray.color = [1.0, 1.0, 1.0];
-            let mut accumulated_light = [0.0, 0.0, 0.0];
-
-            for _ in 0..bounces {
-                let mut closest_hit = Hit::new(f32::INFINITY, [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], 0.0, 0.0);
-                for sphere in &self.spheres {
-                    //println!("speher");
-                    let hit = sphere.intersection(&ray);
-                    if hit.t != -1.0 && hit.t < closest_hit.t {
-                        closest_hit = hit;
+            //light split by how many times it scattered before reaching the camera
+            let mut passes = [[0.0; 3]; 3];
+            let mut scatters = 0;
+            //pdf of the last bounce direction when shadow rays were also aimed at the lights from there
+            let mut bounce_pdf: Option<f32> = None;
+
+            let mut counts = [0; 3];
+            let mut last_bounce: Option<Bounce> = None;
+            let mut surface = None;
+
+            for depth in 0.. {
+                //russian roulette
+                if depth >= settings.min_bounces {
+                    let survival = ray.color[0].max(ray.color[1]).max(ray.color[2]).min(1.0);
+                    if rng.gen::<f32>() >= survival {
+                        break;
                     }
+                    ray.color = ray.color.map(|value| value / survival);
+                }
+                let hit = self.bvh.intersect(&ray, 0.00001, f32::INFINITY);
+                tile.rays += 1;
+                if depth == 0 {
+                    surface = hit.as_ref().map(|hit| Surface {
+                        albedo: hit.color,
+                        normal: hit.normal,
+                        depth: hit.t * dot_product(ray.direction, ray.direction).sqrt(),
+                        object: hit.object,
+                        material: hit.material,
+                    });
                 }
-                for triangle in &self.triangles {
-                    //println!("triangle");
-                    let hit = triangle.intersection(&ray);
-                    if hit.t != -1.0 && hit.t < closest_hit.t {
-                        closest_hit = hit;
+                //area lights end the path, only light from their front counts
+                let t_max = hit.as_ref().map_or(f32::INFINITY, |hit| hit.t);
+                let light_hit = self.lights.iter()
+                    .filter_map(|light| light.intersect(&ray, 0.00001, t_max))
+                    .min_by(|a, b| a.0.total_cmp(&b.0));
+                if !self.volumes.is_empty() {
+                    let t_end = light_hit.as_ref().map_or(t_max, |light_hit| light_hit.0);
+                    match volumes::track(&self.volumes, &ray, t_end, &mut rng) {
+                        MediumEvent::Pass(weight) => ray.color = [0, 1, 2].map(|i| ray.color[i] * weight[i]),
+                        MediumEvent::Absorb => break,
+                        MediumEvent::Scatter { t, weight, anisotropy } => {
+                            if !settings.allows(&mut counts, last_bounce) {
+                                break;
+                            }
+                            last_bounce = Some(Bounce::Diffuse);
+                            scatters += 1;
+                            ray.color = [0, 1, 2].map(|i| ray.color[i] * weight[i]);
+                            let direction = normalize(ray.direction);
+                            let point = [0, 1, 2].map(|i| ray.origin[i] + ray.direction[i] * t);
+                            tile.rays += shadow_rays;
+                            let light = self.direct_light(point, ray.time, &mut rng, false, |light_direction| {
+                                Some(volumes::henyey_greenstein(dot_product(direction, light_direction), anisotropy))
+                            });
+                            add_light(&mut passes, scatters, [0, 1, 2].map(|i| light[i] * ray.color[i]));
+                            ray.origin = point;
+                            ray.direction = volumes::sample_henyey_greenstein(direction, anisotropy, &mut rng);
+                            bounce_pdf = Some(volumes::henyey_greenstein(dot_product(direction, ray.direction), anisotropy));
+                            continue;
+                        }
                     }
                 }
-
-                if closest_hit.t == f32::INFINITY {
+                if let Some((_, radiance, light_pdf)) = light_hit {
+                    let weight = bounce_pdf.map_or(1.0, |pdf| power_heuristic(pdf, light_pdf));
+                    add_light(&mut passes, scatters, [0, 1, 2].map(|i| radiance[i] * ray.color[i] * weight));
+                    break;
+                }
+                let closest_hit = match hit {
+                    Some(hit) => hit,
+                    None => {
+                        let radiance = self.environment.radiance(ray.direction);
+                        let weight = bounce_pdf.map_or(1.0, |pdf| power_heuristic(pdf, self.environment.pdf(ray.direction)));
+                        add_light(&mut passes, scatters, [0, 1, 2].map(|i| radiance[i] * ray.color[i] * weight));
+                        break;
+                    }
+                };
+                bounce_pdf = None;
+                let light_emitted = closest_hit.emission;
+                add_light(&mut passes, scatters, [light_emitted[0] * ray.color[0], light_emitted[1] * ray.color[1], light_emitted[2] * ray.color[2]]);
+                if !settings.allows(&mut counts, last_bounce) {
                     break;
                 }
-                let light_emitted = [closest_hit.color[0] * closest_hit.light, closest_hit.color[1] * closest_hit.light, closest_hit.color[2] * closest_hit.light];
-                accumulated_light = [accumulated_light[0] + light_emitted[0] * ray.color[0], accumulated_light[1] + light_emitted[1] * ray.color[1], accumulated_light[2] + light_emitted[2] * ray.color[2]];
+                scatters += 1;
 
-                ray.color[0] *= closest_hit.color[0] as f32;
-                ray.color[1] *= closest_hit.color[1] as f32;
-                ray.color[2] *= closest_hit.color[2] as f32;
+                let incoming = ray.color;
+                ray.color[0] *= closest_hit.color[0];
+                ray.color[1] *= closest_hit.color[1];
+                ray.color[2] *= closest_hit.color[2];
 
                 // if closest_hit.light > 0.0 {
                 //     break;
                 // }
 
-                ray.origin = closest_hit.location;
+                //see-through materials let some rays pass the surface
+                if closest_hit.opacity < 1.0 && rng.gen::<f32>() >= closest_hit.opacity {
+                    ray.direction = transmission(&mut ray, &closest_hit, &mut rng);
+                    ray.origin = closest_hit.leaving_point(ray.direction);
+                    last_bounce = Some(Bounce::Transmission);
+                    continue;
+                }
                 
                 //not right
                 //ray.direction = closest_hit.normal;
@@ -176,37 +838,59 @@ impl Scene {
                 ray.direction[1] -= 2.0 * dot * closest_hit.normal[1];
                 ray.direction[2] -= 2.0 * dot * closest_hit.normal[2];
                 */
-                //random reflection
+                //random reflection, a random point on the unit sphere sitting on the normal gives directions
+                //weighted by cos, which matches how much light a diffuse surface takes from each one
                 let x = gaussian_random(&mut rng);
                 let y = gaussian_random(&mut rng);
                 let z = gaussian_random(&mut rng);
                 let length = (x.powi(2) + y.powi(2) + z.powi(2)).sqrt();
-                let mut new_ray_direction = [x / length, y / length, z / length];
-                if new_ray_direction[0] * closest_hit.normal[0] + new_ray_direction[1] * closest_hit.normal[1] + new_ray_direction[2] * closest_hit.normal[2] < 0.0 {
-                    new_ray_direction[0] = -new_ray_direction[0];
-                    new_ray_direction[1] = -new_ray_direction[1];
-                    new_ray_direction[2] = -new_ray_direction[2];
+                let normal = closest_hit.normal;
+                let mut new_ray_direction = normalize([normal[0] + x / length, normal[1] + y / length, normal[2] + z / length]);
+                if dot_product(new_ray_direction, normal) <= 0.0 {
+                    new_ray_direction = normal;
                 }
                 if closest_hit.smoothness > 0.0 {
+                    //the diffuse part of the bounce can't find lights that are a single point or direction, so it aims at those
+                    let smoothness = closest_hit.smoothness;
+                    tile.rays += delta_shadow_rays;
+                    let light = self.direct_light(closest_hit.leaving_point(normal), ray.time, &mut rng, true, |light_direction| {
+                        let cos = dot_product(normal, light_direction);
+                        (cos > 0.0).then_some(cos / PI)
+                    });
+                    add_light(&mut passes, scatters, [0, 1, 2].map(|i| light[i] * ray.color[i] * (1.0 - smoothness)));
+                    //the mirror part only takes on the color as much as the surface is metal
+                    let specular_color = closest_hit.color.map(|value| closest_hit.metallic * value + 1.0 - closest_hit.metallic);
+                    ray.color = [0, 1, 2].map(|i| incoming[i] * (closest_hit.color[i] * (1.0 - smoothness) + specular_color[i] * smoothness));
+                    last_bounce = Some(Bounce::Specular);
                     let specular_direction = specular_reflection(&mut ray, &closest_hit);
                     ray.direction = [
-                        new_ray_direction[0] * (1.0 - closest_hit.smoothness) + specular_direction[0] * closest_hit.smoothness,
-                        new_ray_direction[1] * (1.0 - closest_hit.smoothness) + specular_direction[1] * closest_hit.smoothness,
-                        new_ray_direction[2] * (1.0 - closest_hit.smoothness) + specular_direction[2] * closest_hit.smoothness,
+                        new_ray_direction[0] * (1.0 - smoothness) + specular_direction[0] * smoothness,
+                        new_ray_direction[1] * (1.0 - smoothness) + specular_direction[1] * smoothness,
+                        new_ray_direction[2] * (1.0 - smoothness) + specular_direction[2] * smoothness,
                     ];
                 }
                 else {
+                    //purely diffuse, so also aim shadow rays at the lights
+                    tile.rays += shadow_rays;
+                    let light = self.direct_light(closest_hit.leaving_point(normal), ray.time, &mut rng, false, |light_direction| {
+                        let cos = dot_product(normal, light_direction);
+                        (cos > 0.0).then_some(cos / PI)
+                    });
+                    add_light(&mut passes, scatters, [0, 1, 2].map(|i| light[i] * ray.color[i]));
                     ray.direction = new_ray_direction;
+                    bounce_pdf = Some(dot_product(normal, new_ray_direction) / PI);
+                    last_bounce = Some(Bounce::Diffuse);
                 }
+                ray.origin = closest_hit.leaving_point(ray.direction);
             }
-            colour_sum[0] += accumulated_light[0];
-            colour_sum[1] += accumulated_light[1];
-            colour_sum[2] += accumulated_light[2];
-        }
-        [
-            (colour_sum[0] * 255.0 / samples as f32) as u8,
-            (colour_sum[1] * 255.0 / samples as f32) as u8,
-            (colour_sum[2] * 255.0 / samples as f32) as u8,
-        ]
+            let [emission, direct, indirect] = passes;
+            tile.splat(&Sample {
+                position: [x as f32 + antialiasing_x + 0.5, y as f32 + antialiasing_y + 0.5],
+                emission,
+                direct,
+                indirect,
+                surface,
+            });
+        }
     }
 }
\ No newline at end of file
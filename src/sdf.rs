@@ -0,0 +1,250 @@
+use std::f32::consts::PI;
+use rand::prelude::*;
+use super::bvh::Aabb;
+use super::objects::{Hit, Hittable, Material, Ray, SurfaceSample, dot_product, normalize};
+use super::primitives::{self, Transform};
+
+//closer than this to the surface counts as a hit
+const SURFACE_DISTANCE: f32 = 0.0001;
+const MAX_STEPS: usize = 512;
+//offset used when estimating the gradient for normals
+const GRADIENT_STEP: f32 = 0.0002;
+//rays into an unbounded field like an endless repeat give up after this distance
+const MAX_DISTANCE: f32 = 1000.0;
+
+//expression tree of signed distance functions, negative inside
+pub enum Sdf {
+    Sphere { radius: f32 },
+    //half_size includes the rounding, so rounding only softens the corners
+    Box { half_size: [f32; 3], rounding: f32 },
+    //ring in the xz plane
+    Torus { major_radius: f32, minor_radius: f32 },
+    //power 8 gives the usual bulb, it fits in a radius of about 1.2
+    Mandelbulb { power: f32, iterations: usize },
+    Translate { offset: [f32; 3], child: Box<Sdf> },
+    //blends children together over roughly smoothness units, 0 is a plain union
+    SmoothUnion { smoothness: f32, children: Vec<Sdf> },
+    //copies the child every spacing units, count copies each side of the original or forever,
+    //a spacing of 0 leaves that axis alone
+    Repeat { spacing: [f32; 3], count: Option<[f32; 3]>, child: Box<Sdf> },
+    //turns the child around the y axis by rate radians per unit of height
+    Twist { rate: f32, lipschitz: f32, child: Box<Sdf> },
+}
+
+impl Sdf {
+    pub fn twist(rate: f32, child: Sdf) -> Sdf {
+        //twisting stretches space, so distances have to be shrunk by how fast the outermost point moves
+        let radius = child.bounds().map_or(2.0, |bounds| {
+            [bounds.min[0].abs(), bounds.max[0].abs()].into_iter().fold(0.0_f32, f32::max)
+                .hypot([bounds.min[2].abs(), bounds.max[2].abs()].into_iter().fold(0.0_f32, f32::max))
+        });
+        Sdf::Twist {
+            rate,
+            lipschitz: 1.0 + rate.abs() * radius,
+            child: Box::new(child),
+        }
+    }
+
+    pub fn distance(&self, point: [f32; 3]) -> f32 {
+        match self {
+            Sdf::Sphere { radius } => dot_product(point, point).sqrt() - radius,
+            Sdf::Box { half_size, rounding } => {
+                let q = [0, 1, 2].map(|i| point[i].abs() - half_size[i] + rounding);
+                let outside = q.map(|value| value.max(0.0));
+                dot_product(outside, outside).sqrt() + q[0].max(q[1]).max(q[2]).min(0.0) - rounding
+            }
+            Sdf::Torus { major_radius, minor_radius } => {
+                let ring = point[0].hypot(point[2]) - major_radius;
+                ring.hypot(point[1]) - minor_radius
+            }
+            Sdf::Mandelbulb { power, iterations } => mandelbulb(point, *power, *iterations),
+            Sdf::Translate { offset, child } => child.distance([point[0] - offset[0], point[1] - offset[1], point[2] - offset[2]]),
+            Sdf::SmoothUnion { smoothness, children } => {
+                let mut distances = children.iter().map(|child| child.distance(point));
+                let first = distances.next().unwrap_or(f32::INFINITY);
+                distances.fold(first, |a, b| smooth_min(a, b, *smoothness))
+            }
+            Sdf::Repeat { spacing, count, child } => {
+                let mut local = point;
+                for i in 0..3 {
+                    if spacing[i] <= 0.0 {
+                        continue;
+                    }
+                    let mut cell = (point[i] / spacing[i]).round();
+                    if let Some(count) = count {
+                        cell = cell.clamp(-count[i], count[i]);
+                    }
+                    local[i] = point[i] - spacing[i] * cell;
+                }
+                child.distance(local)
+            }
+            Sdf::Twist { rate, lipschitz, child } => {
+                let (sin, cos) = (rate * point[1]).sin_cos();
+                let untwisted = [cos * point[0] - sin * point[2], point[1], sin * point[0] + cos * point[2]];
+                child.distance(untwisted) / lipschitz
+            }
+        }
+    }
+
+    //box around where the distance can be negative, None when it goes on forever
+    pub fn bounds(&self) -> Option<Aabb> {
+        match self {
+            Sdf::Sphere { radius } => Some(Aabb::new([-radius; 3], [*radius; 3])),
+            Sdf::Box { half_size, .. } => Some(Aabb::new(half_size.map(|half| -half), *half_size)),
+            Sdf::Torus { major_radius, minor_radius } => {
+                let outer = major_radius + minor_radius;
+                Some(Aabb::new([-outer, -minor_radius, -outer], [outer, *minor_radius, outer]))
+            }
+            Sdf::Mandelbulb { .. } => Some(Aabb::new([-1.5; 3], [1.5; 3])),
+            Sdf::Translate { offset, child } => child.bounds().map(|bounds| Aabb::new(
+                [bounds.min[0] + offset[0], bounds.min[1] + offset[1], bounds.min[2] + offset[2]],
+                [bounds.max[0] + offset[0], bounds.max[1] + offset[1], bounds.max[2] + offset[2]],
+            )),
+            Sdf::SmoothUnion { smoothness, children } => {
+                let mut bounds = Aabb::empty();
+                for child in children {
+                    bounds = bounds.union(&child.bounds()?);
+                }
+                //blending can only bulge out by a fraction of the smoothness
+                Some(Aabb::new(bounds.min.map(|value| value - smoothness), bounds.max.map(|value| value + smoothness)))
+            }
+            Sdf::Repeat { spacing, count, child } => {
+                let bounds = child.bounds()?;
+                let mut reach = [0.0; 3];
+                for i in 0..3 {
+                    if spacing[i] > 0.0 {
+                        reach[i] = spacing[i] * count.as_ref()?[i];
+                    }
+                }
+                Some(Aabb::new(
+                    [bounds.min[0] - reach[0], bounds.min[1] - reach[1], bounds.min[2] - reach[2]],
+                    [bounds.max[0] + reach[0], bounds.max[1] + reach[1], bounds.max[2] + reach[2]],
+                ))
+            }
+            Sdf::Twist { child, .. } => {
+                let bounds = child.bounds()?;
+                let x = bounds.min[0].abs().max(bounds.max[0].abs());
+                let z = bounds.min[2].abs().max(bounds.max[2].abs());
+                let radius = x.hypot(z);
+                Some(Aabb::new([-radius, bounds.min[1], -radius], [radius, bounds.max[1], radius]))
+            }
+        }
+    }
+
+    //direction the distance grows fastest, which is the outward normal on the surface
+    fn gradient(&self, point: [f32; 3]) -> [f32; 3] {
+        let mut gradient = [0.0; 3];
+        for corner in [[1.0, -1.0, -1.0], [-1.0, -1.0, 1.0], [-1.0, 1.0, -1.0], [1.0, 1.0, 1.0]] {
+            let distance = self.distance([
+                point[0] + corner[0] * GRADIENT_STEP,
+                point[1] + corner[1] * GRADIENT_STEP,
+                point[2] + corner[2] * GRADIENT_STEP,
+            ]);
+            for i in 0..3 {
+                gradient[i] += corner[i] * distance;
+            }
+        }
+        gradient
+    }
+}
+
+//polynomial smooth minimum, equal to min(a, b) once they are more than smoothness apart
+fn smooth_min(a: f32, b: f32, smoothness: f32) -> f32 {
+    if smoothness <= 0.0 {
+        return a.min(b);
+    }
+    let h = (0.5 + 0.5 * (b - a) / smoothness).clamp(0.0, 1.0);
+    b * (1.0 - h) + a * h - smoothness * h * (1.0 - h)
+}
+
+//distance estimate from the rate the iteration escapes
+fn mandelbulb(point: [f32; 3], power: f32, iterations: usize) -> f32 {
+    let mut z = point;
+    let mut derivative = 1.0;
+    let mut radius = 0.0;
+    for _ in 0..iterations {
+        radius = dot_product(z, z).sqrt();
+        if radius > 2.0 {
+            break;
+        }
+        let theta = (z[1] / radius.max(1e-12)).clamp(-1.0, 1.0).acos() * power;
+        let phi = z[2].atan2(z[0]) * power;
+        derivative = radius.powf(power - 1.0) * power * derivative + 1.0;
+        let scaled = radius.powf(power);
+        z = [
+            scaled * theta.sin() * phi.cos() + point[0],
+            scaled * theta.cos() + point[1],
+            scaled * theta.sin() * phi.sin() + point[2],
+        ];
+    }
+    if radius <= 0.0 {
+        return 0.0;
+    }
+    0.5 * radius.ln() * radius / derivative
+}
+
+//shape drawn by stepping along the ray by the distance to the nearest surface until it gets close enough
+pub struct SdfShape {
+    pub transform: Transform,
+    pub root: Sdf,
+    pub material: Material,
+    bounds: Option<Aabb>,
+}
+
+impl SdfShape {
+    pub fn new(transform: Transform, root: Sdf, material: Material) -> SdfShape {
+        SdfShape {
+            transform,
+            bounds: root.bounds(),
+            root,
+            material,
+        }
+    }
+}
+
+impl Hittable for SdfShape {
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+        let (origin, direction) = self.transform.local_ray(ray);
+        //march in units of distance so steps can be taken straight from the field
+        let length = dot_product(direction, direction).sqrt();
+        let unit = direction.map(|value| value / length);
+        let (mut t, end) = match &self.bounds {
+            Some(bounds) => bounds.hit(origin, unit.map(|value| 1.0 / value), t_min * length, t_max * length)?,
+            None => (t_min * length, (t_max * length).min(MAX_DISTANCE)),
+        };
+
+        //a ray leaving the surface first has to get clear of it before hits count, then the side
+        //it is on says whether it marches towards the surface from outside or inside
+        let mut inside = None;
+        for _ in 0..MAX_STEPS {
+            if t > end {
+                return None;
+            }
+            let point = [origin[0] + unit[0] * t, origin[1] + unit[1] * t, origin[2] + unit[2] * t];
+            let distance = self.root.distance(point);
+            let side = match inside {
+                Some(side) => side,
+                None if distance.abs() < SURFACE_DISTANCE => {
+                    t += SURFACE_DISTANCE;
+                    continue;
+                }
+                None => *inside.insert(distance < 0.0),
+            };
+            let towards = if side { -distance } else { distance };
+            if towards < SURFACE_DISTANCE {
+                let normal = self.root.gradient(point);
+                let direction = normalize(normal);
+                let uv = [0.5 + direction[2].atan2(direction[0]) / (2.0 * PI), 0.5 + direction[1].clamp(-1.0, 1.0).asin() / PI];
+                return Some(primitives::finish(&self.transform, &self.material, ray, t / length, point, normal, uv));
+            }
+            t += towards;
+        }
+        None
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        self.bounds.as_ref().map(|bounds| self.transform.bounds_around(bounds))
+    }
+    fn sample_surface(&self, _rng: &mut ThreadRng) -> Option<SurfaceSample> {
+        None
+    }
+}
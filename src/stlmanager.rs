@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::fs;
+use super::objects::{self, cross_product, dot_product, subtract, normalize};
+use super::objmanager::{MeshError, Placement};
+
+//vertices closer than this are merged when building smooth normals
+const WELD_DISTANCE: f32 = 0.00001;
+
+struct Facet {
+    normal: [f32; 3],
+    vertices: [[f32; 3]; 3],
+}
+
+fn parse_ascii(filename: &str, text: &str) -> Result<Vec<Facet>, MeshError> {
+    let mut facets = vec![];
+    let mut normal = [0.0, 0.0, 0.0];
+    let mut vertices = vec![];
+    for (line_number, line) in text.lines().enumerate() {
+        let error = |message: String| MeshError::new(filename, line_number + 1, message);
+        let mut words = line.split_whitespace();
+        let read_vector = |words: &mut std::str::SplitWhitespace| -> Result<[f32; 3], MeshError> {
+            let mut vector = [0.0; 3];
+            for value in &mut vector {
+                let word = words.next().ok_or_else(|| error("missing number".to_string()))?;
+                *value = word.parse().map_err(|_| error(format!("invalid number '{}'", word)))?;
+            }
+            Ok(vector)
+        };
+        match words.next() {
+            Some("facet") => {
+                //facet normal nx ny nz
+                words.next();
+                normal = read_vector(&mut words)?;
+                vertices.clear();
+            }
+            Some("vertex") => vertices.push(read_vector(&mut words)?),
+            Some("endfacet") => {
+                if vertices.len() != 3 {
+                    return Err(error(format!("facet needs 3 vertices, found {}", vertices.len())));
+                }
+                facets.push(Facet {
+                    normal,
+                    vertices: [vertices[0], vertices[1], vertices[2]],
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(facets)
+}
+
+fn parse_binary(filename: &str, bytes: &[u8]) -> Result<Vec<Facet>, MeshError> {
+    //80 byte header, u32 count, then 50 bytes per facet: normal, 3 vertices and an attribute u16
+    let count = u32::from_le_bytes(bytes[80..84].try_into().unwrap()) as usize;
+    if bytes.len() < 84 + count * 50 {
+        return Err(MeshError::new(filename, 0, format!("expected {} facets but the file is too short", count)));
+    }
+    let read_vector = |offset: usize| -> [f32; 3] {
+        let value = |index: usize| f32::from_le_bytes(bytes[offset + index * 4..offset + index * 4 + 4].try_into().unwrap());
+        [value(0), value(1), value(2)]
+    };
+    let mut facets = Vec::with_capacity(count);
+    for i in 0..count {
+        let offset = 84 + i * 50;
+        facets.push(Facet {
+            normal: read_vector(offset),
+            vertices: [read_vector(offset + 12), read_vector(offset + 24), read_vector(offset + 36)],
+        });
+    }
+    Ok(facets)
+}
+
+//read from .stl file, ascii or binary. faces within smooth_angle degrees of each other
+//get averaged normals where they share a welded vertex, 0 keeps every facet flat
+pub fn extract_triangles(filename: &str, placement: &Placement, material: objects::Material, smooth_angle: f32) -> Result<Vec<objects::Triangle>, MeshError> {
+    let bytes = fs::read(filename).map_err(|error| MeshError::new(filename, 0, error.to_string()))?;
+    //some binary exporters also start their header with "solid", so trust the size first
+    let binary_size = bytes.get(80..84).map(|count| 84 + u32::from_le_bytes(count.try_into().unwrap()) as usize * 50);
+    let mut facets = if binary_size == Some(bytes.len()) || !bytes.starts_with(b"solid") {
+        if bytes.len() < 84 {
+            return Err(MeshError::new(filename, 0, "file is too short for a binary stl".to_string()));
+        }
+        parse_binary(filename, &bytes)?
+    } else {
+        parse_ascii(filename, &String::from_utf8_lossy(&bytes))?
+    };
+
+    //the stored normal decides which way the face points, the winding is flipped to agree with it
+    let mut face_normals = Vec::with_capacity(facets.len());
+    for facet in &mut facets {
+        let winding = cross_product(subtract(facet.vertices[1], facet.vertices[0]), subtract(facet.vertices[2], facet.vertices[0]));
+        if dot_product(winding, facet.normal) < 0.0 {
+            facet.vertices.swap(1, 2);
+        }
+        //the cross product length is twice the area, so bigger faces weigh more when smoothing
+        face_normals.push(if dot_product(winding, facet.normal) < 0.0 { [-winding[0], -winding[1], -winding[2]] } else { winding });
+    }
+
+    let mut welded: HashMap<[i64; 3], usize> = HashMap::new();
+    let mut corners = Vec::with_capacity(facets.len());
+    let mut adjacent: Vec<Vec<usize>> = vec![];
+    for (face, facet) in facets.iter().enumerate() {
+        let indices = facet.vertices.map(|vertex| {
+            let key = vertex.map(|value| (value / WELD_DISTANCE).round() as i64);
+            let next = welded.len();
+            let index = *welded.entry(key).or_insert(next);
+            if index == adjacent.len() {
+                adjacent.push(vec![]);
+            }
+            adjacent[index].push(face);
+            index
+        });
+        corners.push(indices);
+    }
+
+    let smooth_cos = smooth_angle.to_radians().cos();
+    let mut triangles = Vec::with_capacity(facets.len());
+    for (face, facet) in facets.iter().enumerate() {
+        let mut triangle = objects::Triangle::new(facet.vertices, [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]], material.clone());
+        if smooth_angle > 0.0 {
+            let own = normalize(face_normals[face]);
+            triangle.vertex_normals = Some(corners[face].map(|vertex| {
+                let mut sum = [0.0, 0.0, 0.0];
+                for &other in &adjacent[vertex] {
+                    if dot_product(own, normalize(face_normals[other])) >= smooth_cos {
+                        sum = [sum[0] + face_normals[other][0], sum[1] + face_normals[other][1], sum[2] + face_normals[other][2]];
+                    }
+                }
+                normalize(sum)
+            }));
+        }
+        triangles.push(triangle);
+    }
+    Ok(placement.apply(triangles))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //writes an stl under the temp directory, named after the test so tests running at once don't share it
+    fn load(name: &str, contents: &[u8], smooth_angle: f32) -> Result<Vec<objects::Triangle>, MeshError> {
+        let path = std::env::temp_dir().join(format!("stlmanager_{}_{}.stl", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let filename = path.to_string_lossy().into_owned();
+        let triangles = extract_triangles(&filename, &Placement::new([0.0; 3], [1.0; 3]), objects::Material::new([1.0; 3], 0.0, 0.0), smooth_angle);
+        fs::remove_file(&filename).unwrap();
+        triangles
+    }
+
+    fn ascii(facets: &[([f32; 3], [[f32; 3]; 3])]) -> Vec<u8> {
+        let mut text = String::from("solid test\n");
+        for (normal, vertices) in facets {
+            text += &format!("facet normal {} {} {}\nouter loop\n", normal[0], normal[1], normal[2]);
+            for vertex in vertices {
+                text += &format!("vertex {} {} {}\n", vertex[0], vertex[1], vertex[2]);
+            }
+            text += "endloop\nendfacet\n";
+        }
+        text += "endsolid test\n";
+        text.into_bytes()
+    }
+
+    fn binary(header: &[u8], facets: &[([f32; 3], [[f32; 3]; 3])]) -> Vec<u8> {
+        let mut bytes = header.to_vec();
+        bytes.resize(80, b' ');
+        bytes.extend((facets.len() as u32).to_le_bytes());
+        for (normal, vertices) in facets {
+            for vector in std::iter::once(normal).chain(vertices) {
+                bytes.extend(vector.iter().flat_map(|value| value.to_le_bytes()));
+            }
+            bytes.extend([0, 0]);
+        }
+        bytes
+    }
+
+    const TRIANGLE: ([f32; 3], [[f32; 3]; 3]) = ([0.0, 0.0, 1.0], [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+
+    #[test]
+    fn ascii_and_binary_are_told_apart() {
+        let from_ascii = load("ascii", &ascii(&[TRIANGLE]), 0.0).unwrap();
+        assert_eq!(from_ascii.len(), 1);
+        assert_eq!(from_ascii[0].vertices, TRIANGLE.1);
+        let from_binary = load("binary", &binary(b"exported by something", &[TRIANGLE, TRIANGLE]), 0.0).unwrap();
+        assert_eq!(from_binary.len(), 2);
+        assert_eq!(from_binary[1].vertices, TRIANGLE.1);
+    }
+
+    #[test]
+    fn binary_files_can_start_with_solid() {
+        let triangles = load("solid_header", &binary(b"solid part, binary anyway", &[TRIANGLE]), 0.0).unwrap();
+        assert_eq!(triangles.len(), 1);
+        assert_eq!(triangles[0].vertices, TRIANGLE.1);
+    }
+
+    #[test]
+    fn winding_follows_the_stored_normal() {
+        let flipped = ([0.0, 0.0, -1.0], TRIANGLE.1);
+        let triangles = load("winding", &ascii(&[TRIANGLE, flipped]), 0.0).unwrap();
+        assert_eq!(triangles[0].vertices, TRIANGLE.1);
+        assert_eq!(triangles[1].vertices, [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]]);
+    }
+
+    //two faces meeting at a right angle along the x axis, the second one's copy of the edge is a hair off
+    fn fold() -> Vec<u8> {
+        ascii(&[
+            ([0.0, 0.0, 1.0], [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]),
+            ([0.0, -1.0, 0.0], [[0.000001, 0.0, 0.0], [1.000001, 0.0, 0.0], [0.0, 0.0, 1.0]]),
+        ])
+    }
+
+    #[test]
+    fn close_vertices_are_welded() {
+        let triangles = load("weld", &fold(), 180.0).unwrap();
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        let shared = [0.0, -half, half];
+        let first = triangles[0].vertex_normals.unwrap();
+        let second = triangles[1].vertex_normals.unwrap();
+        for normal in [first[0], first[1], second[0], second[1]] {
+            for i in 0..3 {
+                assert!((normal[i] - shared[i]).abs() < 1e-5, "{:?} is not shared across the edge", normal);
+            }
+        }
+        //corners off the shared edge only touch their own face
+        assert_eq!(first[2], [0.0, 0.0, 1.0]);
+        assert_eq!(second[2], [0.0, -1.0, 0.0]);
+    }
+
+    #[test]
+    fn faces_past_the_smoothing_angle_stay_sharp() {
+        let triangles = load("sharp", &fold(), 60.0).unwrap();
+        assert!(triangles[0].vertex_normals.unwrap().iter().all(|&normal| normal == [0.0, 0.0, 1.0]));
+        assert!(triangles[1].vertex_normals.unwrap().iter().all(|&normal| normal == [0.0, -1.0, 0.0]));
+        let flat = load("flat", &fold(), 0.0).unwrap();
+        assert!(flat.iter().all(|triangle| triangle.vertex_normals.is_none()));
+    }
+}
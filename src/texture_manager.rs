@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    pub fn from_name(name: &str) -> WrapMode {
+        match name {
+            "clamp" => WrapMode::Clamp,
+            "mirror" => WrapMode::Mirror,
+            _ => WrapMode::Repeat,
+        }
+    }
+    fn apply(&self, index: i64, size: usize) -> usize {
+        let size = size as i64;
+        match self {
+            WrapMode::Repeat => index.rem_euclid(size) as usize,
+            WrapMode::Clamp => index.clamp(0, size - 1) as usize,
+            WrapMode::Mirror => {
+                let period = index.rem_euclid(size * 2);
+                if period < size {
+                    period as usize
+                } else {
+                    (size * 2 - 1 - period) as usize
+                }
+            }
+        }
+    }
+}
+
+//what the numbers in an 8 or 16 bit image mean, colors are stored with the srgb curve while data like roughness is
+//stored as it is. hdr files are always linear
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+impl ColorSpace {
+    fn decode(&self, value: f32) -> f32 {
+        match self {
+            ColorSpace::Linear => value,
+            ColorSpace::Srgb if value <= 0.04045 => value / 12.92,
+            ColorSpace::Srgb => ((value + 0.055) / 1.055).powf(2.4),
+        }
+    }
+}
+
+//texels are stored as linear floats in 0..1 (or above for hdr), row 0 is the top of the image
+pub struct Texture {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<[f32; 3]>,
+    pub wrap: WrapMode,
+}
+
+impl Texture {
+    pub fn new(width: usize, height: usize, data: Vec<[f32; 3]>, wrap: WrapMode) -> Texture {
+        Texture {
+            width,
+            height,
+            data,
+            wrap,
+        }
+    }
+    //.hdr files are read as radiance rgbe, .jpg and .jpeg as jpeg, anything else as png
+    pub fn load(filename: &str, wrap: WrapMode, color_space: ColorSpace) -> Result<Texture, String> {
+        let mut file = File::open(filename).map_err(|error| error.to_string())?;
+        let lowercase = filename.to_lowercase();
+        if lowercase.ends_with(".hdr") {
+            let mut bytes = vec![];
+            file.read_to_end(&mut bytes).map_err(|error| error.to_string()).and_then(|_| decode_hdr(&bytes, wrap))
+        } else if lowercase.ends_with(".jpg") || lowercase.ends_with(".jpeg") {
+            decode_jpeg(file, wrap, color_space)
+        } else {
+            decode_png(file, wrap, color_space)
+        }
+    }
+    pub fn texel(&self, x: i64, y: i64) -> [f32; 3] {
+        let x = self.wrap.apply(x, self.width);
+        let y = self.wrap.apply(y, self.height);
+        self.data[y * self.width + x]
+    }
+    //bilinear lookup, v = 0 is the bottom of the image like in .obj files
+    pub fn sample(&self, uv: [f32; 2]) -> [f32; 3] {
+        let x = uv[0] * self.width as f32 - 0.5;
+        let y = (1.0 - uv[1]) * self.height as f32 - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        let x0 = x0 as i64;
+        let y0 = y0 as i64;
+
+        let c00 = self.texel(x0, y0);
+        let c10 = self.texel(x0 + 1, y0);
+        let c01 = self.texel(x0, y0 + 1);
+        let c11 = self.texel(x0 + 1, y0 + 1);
+        let mut color = [0.0; 3];
+        for i in 0..3 {
+            let top = c00[i] * (1.0 - fx) + c10[i] * fx;
+            let bottom = c01[i] * (1.0 - fx) + c11[i] * fx;
+            color[i] = top * (1.0 - fy) + bottom * fy;
+        }
+        color
+    }
+}
+
+//textures are shared between every primitive and thread that uses them
+#[derive(Default)]
+pub struct TextureCache {
+    textures: HashMap<(String, WrapMode, ColorSpace), Arc<Texture>>,
+}
+
+impl TextureCache {
+    pub fn new() -> TextureCache {
+        TextureCache {
+            textures: HashMap::new(),
+        }
+    }
+    pub fn load(&mut self, filename: &str, wrap: WrapMode, color_space: ColorSpace) -> Result<Arc<Texture>, String> {
+        let key = (filename.to_string(), wrap, color_space);
+        if let Some(texture) = self.textures.get(&key) {
+            return Ok(texture.clone());
+        }
+        let texture = Arc::new(Texture::load(filename, wrap, color_space)?);
+        self.textures.insert(key, texture.clone());
+        Ok(texture)
+    }
+    //for images stored inside other files, name only has to be unique, jpeg or png is told apart by the first bytes
+    pub fn load_bytes(&mut self, name: &str, bytes: &[u8], wrap: WrapMode, color_space: ColorSpace) -> Result<Arc<Texture>, String> {
+        let key = (name.to_string(), wrap, color_space);
+        if let Some(texture) = self.textures.get(&key) {
+            return Ok(texture.clone());
+        }
+        let texture = if bytes.starts_with(&[0xFF, 0xD8]) {
+            decode_jpeg(bytes, wrap, color_space)?
+        } else {
+            decode_png(bytes, wrap, color_space)?
+        };
+        let texture = Arc::new(texture);
+        self.textures.insert(key, texture.clone());
+        Ok(texture)
+    }
+}
+
+fn decode_png<R: Read>(source: R, wrap: WrapMode, color_space: ColorSpace) -> Result<Texture, String> {
+    let mut decoder = png::Decoder::new(source);
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(|error| error.to_string())?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|error| error.to_string())?;
+
+    let channels = info.color_type.samples();
+    let sixteen_bit = info.bit_depth == png::BitDepth::Sixteen;
+    let bytes_per_sample = if sixteen_bit { 2 } else { 1 };
+    let value = |index: usize| -> f32 {
+        let value = if sixteen_bit {
+            u16::from_be_bytes([buffer[index * 2], buffer[index * 2 + 1]]) as f32 / 65535.0
+        } else {
+            buffer[index] as f32 / 255.0
+        };
+        color_space.decode(value)
+    };
+
+    let width = info.width as usize;
+    let height = info.height as usize;
+    let mut data = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let row = y * info.line_size / bytes_per_sample;
+        for x in 0..width {
+            let index = row + x * channels;
+            let color = match info.color_type {
+                png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha => {
+                    let v = value(index);
+                    [v, v, v]
+                }
+                _ => [value(index), value(index + 1), value(index + 2)],
+            };
+            data.push(color);
+        }
+    }
+    Ok(Texture::new(width, height, data, wrap))
+}
+
+fn decode_jpeg<R: Read>(source: R, wrap: WrapMode, color_space: ColorSpace) -> Result<Texture, String> {
+    let mut decoder = jpeg_decoder::Decoder::new(std::io::BufReader::new(source));
+    let pixels = decoder.decode().map_err(|error| error.to_string())?;
+    let info = decoder.info().ok_or("jpeg has no image")?;
+    let value = |byte: u8| color_space.decode(byte as f32 / 255.0);
+
+    let width = info.width as usize;
+    let height = info.height as usize;
+    let data = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => pixels.iter().map(|&byte| {
+            let v = value(byte);
+            [v, v, v]
+        }).collect(),
+        jpeg_decoder::PixelFormat::RGB24 => pixels.chunks_exact(3).map(|rgb| [value(rgb[0]), value(rgb[1]), value(rgb[2])]).collect(),
+        other => return Err(format!("unsupported jpeg pixel format {:?}, only 8 bit grey and rgb are read", other)),
+    };
+    Ok(Texture::new(width, height, data, wrap))
+}
+
+//radiance .hdr, a text header then rows of shared exponent rgbe pixels that are usually run length encoded
+fn decode_hdr(bytes: &[u8], wrap: WrapMode) -> Result<Texture, String> {
+    let mut position = 0;
+    let mut next_line = || -> Result<String, String> {
+        let end = bytes[position..].iter().position(|&byte| byte == b'\n').ok_or("header has no end")?;
+        let line = String::from_utf8_lossy(&bytes[position..position + end]).trim().to_string();
+        position += end + 1;
+        Ok(line)
+    };
+    let magic = next_line()?;
+    if !magic.starts_with("#?") {
+        return Err("not a radiance hdr file".to_string());
+    }
+    loop {
+        let line = next_line()?;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+            return Err(format!("unsupported {}", line));
+        }
+    }
+    //only the standard top to bottom, left to right orientation is supported
+    let resolution = next_line()?;
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<&str>>().as_slice() {
+        ["-Y", height, "+X", width] => (
+            height.parse::<usize>().map_err(|_| "invalid height")?,
+            width.parse::<usize>().map_err(|_| "invalid width")?,
+        ),
+        _ => return Err(format!("unsupported resolution line '{}'", resolution)),
+    };
+
+    let mut data = Vec::with_capacity(width * height);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        let byte = |index: usize| bytes.get(index).copied().ok_or("unexpected end of file");
+        let header = [byte(position)?, byte(position + 1)?, byte(position + 2)?, byte(position + 3)?];
+        let encoded = header[0] == 2 && header[1] == 2 && header[2] & 0x80 == 0 && (8..0x8000).contains(&width);
+        if encoded {
+            if ((header[2] as usize) << 8 | header[3] as usize) != width {
+                return Err("scanline width does not match the image".to_string());
+            }
+            position += 4;
+            //each of the four channels is stored separately as runs and literal spans
+            for channel in 0..4 {
+                let mut x = 0;
+                while x < width {
+                    let count = byte(position)? as usize;
+                    position += 1;
+                    if count > 128 {
+                        let count = count - 128;
+                        let value = byte(position)?;
+                        position += 1;
+                        for pixel in scanline.iter_mut().skip(x).take(count) {
+                            pixel[channel] = value;
+                        }
+                        x += count;
+                    } else {
+                        if count == 0 || x + count > width {
+                            return Err("bad run length".to_string());
+                        }
+                        for offset in 0..count {
+                            scanline[x + offset][channel] = byte(position + offset)?;
+                        }
+                        position += count;
+                        x += count;
+                    }
+                }
+            }
+        } else {
+            for pixel in scanline.iter_mut() {
+                *pixel = [byte(position)?, byte(position + 1)?, byte(position + 2)?, byte(position + 3)?];
+                position += 4;
+            }
+        }
+        for pixel in &scanline {
+            if pixel[3] == 0 {
+                data.push([0.0, 0.0, 0.0]);
+                continue;
+            }
+            let scale = 2.0_f32.powi(pixel[3] as i32 - 136);
+            data.push([pixel[0] as f32 * scale, pixel[1] as f32 * scale, pixel[2] as f32 * scale]);
+        }
+    }
+    Ok(Texture::new(width, height, data, wrap))
+}
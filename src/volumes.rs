@@ -0,0 +1,351 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+use rand::prelude::*;
+use super::objects::{Hittable, Ray, cross_product, dot_product, normalize};
+use super::primitives::Transform;
+use super::voxelmanager::VoxelGrid;
+
+//rays crossing a boundary more often than this are assumed to be stuck on a broken mesh
+const MAX_CROSSINGS: usize = 64;
+
+//coefficients are per unit of distance, anisotropy is the henyey-greenstein g from -1 (back) to 1 (forward)
+#[derive(Clone, Copy)]
+pub struct Medium {
+    pub absorption: [f32; 3],
+    pub scattering: [f32; 3],
+    pub anisotropy: f32,
+}
+
+impl Medium {
+    pub fn new(absorption: [f32; 3], scattering: [f32; 3], anisotropy: f32) -> Medium {
+        Medium {
+            absorption,
+            scattering,
+            anisotropy: anisotropy.clamp(-0.99, 0.99),
+        }
+    }
+    fn extinction(&self) -> [f32; 3] {
+        [0, 1, 2].map(|i| self.absorption[i] + self.scattering[i])
+    }
+}
+
+//how thick a volume's medium is from place to place, the coefficients are multiplied by it
+#[derive(Clone)]
+pub enum Density {
+    //voxel grid stretched over a box of size centered on the transform
+    Grid { transform: Transform, size: [f32; 3], grid: Arc<VoxelGrid> },
+    //fractal value noise in world space, max(0, noise * contrast + bias) with the noise between -1 and 1
+    Noise { frequency: f32, octaves: usize, contrast: f32, bias: f32, seed: u32 },
+}
+
+impl Density {
+    fn at(&self, point: [f32; 3]) -> f32 {
+        match self {
+            Density::Grid { transform, size, grid } => grid.sample(grid_position(transform, *size, point)),
+            Density::Noise { frequency, octaves, contrast, bias, seed } => {
+                (fractal_noise(point.map(|value| value * frequency), *octaves, *seed) * contrast + bias).max(0.0)
+            }
+        }
+    }
+    //no smaller than the density anywhere in the same majorant cell as point
+    fn majorant(&self, point: [f32; 3]) -> f32 {
+        match self {
+            Density::Grid { transform, size, grid } => grid.majorant(grid_position(transform, *size, point)),
+            Density::Noise { contrast, bias, .. } => (contrast.abs() + bias).max(0.0),
+        }
+    }
+    //distances along the ray between start and end where it moves into another majorant cell
+    fn crossings(&self, ray: &Ray, start: f32, end: f32) -> Vec<f32> {
+        let Density::Grid { transform, size, grid } = self else {
+            return vec![];
+        };
+        let (origin, direction) = transform.local_ray(ray);
+        let mut crossings = vec![];
+        for axis in 0..3 {
+            if direction[axis] == 0.0 {
+                continue;
+            }
+            for edge in grid.cell_edges(axis) {
+                let t = ((edge - 0.5) * size[axis] - origin[axis]) / direction[axis];
+                if start < t && t < end {
+                    crossings.push(t);
+                }
+            }
+        }
+        crossings
+    }
+}
+
+//where point sits in the grid from 0 to 1 on each axis
+fn grid_position(transform: &Transform, size: [f32; 3], point: [f32; 3]) -> [f32; 3] {
+    let local = transform.point_to_local(point);
+    [0, 1, 2].map(|i| local[i] / size[i] + 0.5)
+}
+
+//repeatable pseudo random value from -1 to 1 for a lattice point
+fn lattice_value(x: i32, y: i32, z: i32, seed: u32) -> f32 {
+    let mut hash = seed.wrapping_mul(0x9e3779b9)
+        ^ (x as u32).wrapping_mul(0x85ebca6b)
+        ^ (y as u32).wrapping_mul(0xc2b2ae35)
+        ^ (z as u32).wrapping_mul(0x27d4eb2f);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x2c1b3c6d);
+    hash ^= hash >> 12;
+    hash = hash.wrapping_mul(0x297a2d39);
+    hash ^= hash >> 15;
+    hash as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+fn value_noise(point: [f32; 3], seed: u32) -> f32 {
+    let base = point.map(|value| value.floor());
+    //smoothed so the lattice doesn't show up as creases
+    let fraction = [0, 1, 2].map(|i| {
+        let t = point[i] - base[i];
+        t * t * (3.0 - 2.0 * t)
+    });
+    let [x, y, z] = base.map(|value| value as i32);
+    let mut value = 0.0;
+    for corner in 0..8 {
+        let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+        let weight: f32 = (0..3).map(|i| if offset[i] == 1 { fraction[i] } else { 1.0 - fraction[i] }).product();
+        value += weight * lattice_value(x + offset[0], y + offset[1], z + offset[2], seed);
+    }
+    value
+}
+
+//octaves of noise at doubling frequencies and halving strength, kept between -1 and 1
+fn fractal_noise(point: [f32; 3], octaves: usize, seed: u32) -> f32 {
+    let mut total = 0.0;
+    let mut amplitude = 1.0;
+    let mut amplitude_sum = 0.0;
+    let mut point = point;
+    for octave in 0..octaves.max(1) {
+        total += value_noise(point, seed.wrapping_add(octave as u32)) * amplitude;
+        amplitude_sum += amplitude;
+        amplitude *= 0.5;
+        point = point.map(|value| value * 2.0);
+    }
+    total / amplitude_sum
+}
+
+//medium filling the inside of a closed shape, the boundary is never drawn
+#[derive(Clone)]
+pub struct Volume {
+    pub boundary: Arc<dyn Hittable>,
+    pub medium: Medium,
+    //None is the same thickness all the way through
+    pub density: Option<Density>,
+}
+
+impl Volume {
+    pub fn new(boundary: Arc<dyn Hittable>, medium: Medium) -> Volume {
+        Volume {
+            boundary,
+            medium,
+            density: None,
+        }
+    }
+
+    //stretches of the ray inside the boundary, a ray that leaves without having entered started inside
+    fn intervals(&self, ray: &Ray, t_end: f32) -> Vec<(f32, f32)> {
+        let mut intervals = vec![];
+        let mut entered = None;
+        let mut t = 0.00001;
+        for _ in 0..MAX_CROSSINGS {
+            let Some(hit) = self.boundary.intersect(ray, t, f32::INFINITY) else {
+                break;
+            };
+            if hit.front_face {
+                entered = Some(hit.t);
+            } else {
+                intervals.push((entered.take().unwrap_or(0.0), hit.t));
+            }
+            if hit.t >= t_end {
+                break;
+            }
+            t = hit.t;
+        }
+        if let Some(start) = entered {
+            intervals.push((start, f32::INFINITY));
+        }
+        intervals.into_iter()
+            .map(|(start, end)| (start, end.min(t_end)))
+            .filter(|(start, end)| start < end)
+            .collect()
+    }
+
+    fn density_at(&self, point: [f32; 3]) -> f32 {
+        self.density.as_ref().map_or(1.0, |density| density.at(point))
+    }
+}
+
+pub enum MediumEvent {
+    //made it to t_end, throughput is scaled by the weight
+    Pass([f32; 3]),
+    //scattered at t, the weight is already included
+    Scatter { t: f32, weight: [f32; 3], anisotropy: f32 },
+    Absorb,
+}
+
+//piece of a ray passing through the same volumes and majorant cells all the way along
+struct Segment<'a> {
+    start: f32,
+    end: f32,
+    volumes: Vec<&'a Volume>,
+    //no smaller than the extinction of any channel anywhere along it
+    majorant: f32,
+    //every volume in it is the same thickness throughout, so it can be worked out exactly
+    uniform: bool,
+}
+
+//splits the ray up to t_end into segments, cutting wherever it crosses a boundary or majorant cell
+fn segments<'a>(volumes: &'a [Volume], ray: &Ray, t_end: f32) -> Vec<Segment<'a>> {
+    let intervals: Vec<Vec<(f32, f32)>> = volumes.iter().map(|volume| volume.intervals(ray, t_end)).collect();
+    let mut cuts: Vec<f32> = intervals.iter().flatten().flat_map(|&(start, end)| [start, end]).collect();
+    for (volume, intervals) in volumes.iter().zip(&intervals) {
+        if let Some(density) = &volume.density {
+            for &(start, end) in intervals {
+                cuts.extend(density.crossings(ray, start, end));
+            }
+        }
+    }
+    cuts.sort_by(f32::total_cmp);
+    cuts.dedup();
+
+    let mut segments = vec![];
+    for pair in cuts.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        let middle = if end.is_finite() { (start + end) / 2.0 } else { start + 1.0 };
+        let point = [0, 1, 2].map(|i| ray.origin[i] + ray.direction[i] * middle);
+        let mut segment = Segment {
+            start,
+            end,
+            volumes: vec![],
+            majorant: 0.0,
+            uniform: true,
+        };
+        for (volume, intervals) in volumes.iter().zip(&intervals) {
+            if intervals.iter().any(|&(start, end)| start <= middle && middle < end) {
+                let extinction = volume.medium.extinction();
+                let densest = volume.density.as_ref().map_or(1.0, |density| density.majorant(point));
+                segment.majorant += extinction[0].max(extinction[1]).max(extinction[2]) * densest;
+                segment.uniform &= volume.density.is_none();
+                segment.volumes.push(volume);
+            }
+        }
+        if !segment.volumes.is_empty() {
+            segments.push(segment);
+        }
+    }
+    segments
+}
+
+//what the volumes in a segment add up to at a point, overlapping volumes add together
+fn medium_at(volumes: &[&Volume], point: [f32; 3]) -> Medium {
+    let mut combined = Medium::new([0.0; 3], [0.0; 3], 0.0);
+    let mut weighted_anisotropy = 0.0;
+    for volume in volumes {
+        let density = volume.density_at(point);
+        let medium = volume.medium;
+        combined.absorption = [0, 1, 2].map(|i| combined.absorption[i] + medium.absorption[i] * density);
+        combined.scattering = [0, 1, 2].map(|i| combined.scattering[i] + medium.scattering[i] * density);
+        weighted_anisotropy += medium.anisotropy * average(medium.scattering) * density;
+    }
+    if average(combined.scattering) > 0.0 {
+        combined.anisotropy = weighted_anisotropy / average(combined.scattering);
+    }
+    combined
+}
+
+fn average(values: [f32; 3]) -> f32 {
+    (values[0] + values[1] + values[2]) / 3.0
+}
+
+//delta tracking against each segment's majorant, null collisions make up the difference to the real extinction
+pub fn track(volumes: &[Volume], ray: &Ray, t_end: f32, rng: &mut ThreadRng) -> MediumEvent {
+    let length = dot_product(ray.direction, ray.direction).sqrt();
+    let mut weight = [1.0; 3];
+    for segment in segments(volumes, ray, t_end) {
+        if segment.majorant <= 0.0 {
+            continue;
+        }
+        let majorant = segment.majorant;
+        let mut t = segment.start;
+        loop {
+            t += -(1.0 - rng.gen::<f32>()).ln() / majorant / length;
+            if t >= segment.end {
+                break;
+            }
+            let medium = medium_at(&segment.volumes, [0, 1, 2].map(|i| ray.origin[i] + ray.direction[i] * t));
+            let extinction = medium.extinction();
+            let absorb_chance = average(medium.absorption) / majorant;
+            let scatter_chance = average(medium.scattering) / majorant;
+            let null_chance = (1.0 - absorb_chance - scatter_chance).max(0.0);
+            let pick = rng.gen::<f32>();
+            if pick < absorb_chance {
+                return MediumEvent::Absorb;
+            }
+            if pick < absorb_chance + scatter_chance {
+                weight = [0, 1, 2].map(|i| weight[i] * medium.scattering[i] / (majorant * scatter_chance));
+                return MediumEvent::Scatter { t, weight, anisotropy: medium.anisotropy };
+            }
+            weight = [0, 1, 2].map(|i| weight[i] * (majorant - extinction[i]) / (majorant * null_chance));
+        }
+    }
+    MediumEvent::Pass(weight)
+}
+
+//fraction of light getting through the media up to t_end, exact where the media are uniform and by ratio tracking elsewhere
+pub fn transmittance(volumes: &[Volume], ray: &Ray, t_end: f32, rng: &mut ThreadRng) -> [f32; 3] {
+    let length = dot_product(ray.direction, ray.direction).sqrt();
+    let mut transmittance = [1.0; 3];
+    for segment in segments(volumes, ray, t_end) {
+        if segment.uniform {
+            let extinction = medium_at(&segment.volumes, ray.origin).extinction();
+            let distance = (segment.end - segment.start) * length;
+            transmittance = [0, 1, 2].map(|i| if extinction[i] > 0.0 { transmittance[i] * (-extinction[i] * distance).exp() } else { transmittance[i] });
+            continue;
+        }
+        if segment.majorant <= 0.0 {
+            continue;
+        }
+        let mut t = segment.start;
+        loop {
+            t += -(1.0 - rng.gen::<f32>()).ln() / segment.majorant / length;
+            if t >= segment.end {
+                break;
+            }
+            let extinction = medium_at(&segment.volumes, [0, 1, 2].map(|i| ray.origin[i] + ray.direction[i] * t)).extinction();
+            transmittance = [0, 1, 2].map(|i| transmittance[i] * (1.0 - extinction[i] / segment.majorant).max(0.0));
+            if transmittance.iter().all(|&value| value <= 0.0) {
+                break;
+            }
+        }
+    }
+    transmittance
+}
+
+//share of scattered light going off at an angle with the given cos to the way it was travelling, per steradian
+pub fn henyey_greenstein(cos: f32, anisotropy: f32) -> f32 {
+    let g = anisotropy;
+    let denominator = 1.0 + g * g - 2.0 * g * cos;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+}
+
+//new direction distributed exactly like henyey_greenstein around the unit direction
+pub fn sample_henyey_greenstein(direction: [f32; 3], anisotropy: f32, rng: &mut ThreadRng) -> [f32; 3] {
+    let g = anisotropy;
+    let u = rng.gen::<f32>();
+    let cos = if g.abs() < 1e-3 {
+        1.0 - 2.0 * u
+    } else {
+        let square = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+        ((1.0 + g * g - square * square) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+    let sin = (1.0 - cos * cos).max(0.0).sqrt();
+    let angle = 2.0 * PI * rng.gen::<f32>();
+    let axis = if direction[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+    let u = normalize(cross_product(axis, direction));
+    let v = cross_product(direction, u);
+    [0, 1, 2].map(|i| u[i] * sin * angle.cos() + v[i] * sin * angle.sin() + direction[i] * cos)
+}
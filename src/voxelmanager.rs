@@ -0,0 +1,114 @@
+use std::fs;
+
+//voxels along each side of a cell of the majorant grid
+const MAJORANT_CELL: usize = 8;
+
+//dense grid of densities, x changes fastest then y then z
+pub struct VoxelGrid {
+    pub resolution: [usize; 3],
+    pub data: Vec<f32>,
+    //largest density each block of voxels can interpolate to, lets tracking take long steps through thin parts
+    pub majorants: Vec<f32>,
+    pub majorant_resolution: [usize; 3],
+}
+
+impl VoxelGrid {
+    //the file is a line reading VOXELS, a line with the x y z resolution, then the values as little endian 32 bit floats
+    pub fn load(filename: &str) -> VoxelGrid {
+        let bytes = fs::read(filename).expect("Voxel file not found");
+        decode(&bytes).unwrap_or_else(|error| panic!("Error while reading voxel grid {}: {}", filename, error))
+    }
+
+    pub fn new(resolution: [usize; 3], data: Vec<f32>) -> VoxelGrid {
+        let majorant_resolution = resolution.map(|count| count.div_ceil(MAJORANT_CELL));
+        let mut majorants = vec![0.0_f32; majorant_resolution.iter().product()];
+        for z in 0..resolution[2] {
+            for y in 0..resolution[1] {
+                for x in 0..resolution[0] {
+                    let value = data[(z * resolution[1] + y) * resolution[0] + x];
+                    //interpolation blends in the next voxel over, so each voxel also counts towards the cells it borders
+                    let cells = [x, y, z].map(|index| {
+                        (index.saturating_sub(1) / MAJORANT_CELL, (index + 1) / MAJORANT_CELL)
+                    });
+                    for cell_z in cells[2].0..=cells[2].1.min(majorant_resolution[2] - 1) {
+                        for cell_y in cells[1].0..=cells[1].1.min(majorant_resolution[1] - 1) {
+                            for cell_x in cells[0].0..=cells[0].1.min(majorant_resolution[0] - 1) {
+                                let cell = &mut majorants[(cell_z * majorant_resolution[1] + cell_y) * majorant_resolution[0] + cell_x];
+                                *cell = cell.max(value);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        VoxelGrid {
+            resolution,
+            data,
+            majorants,
+            majorant_resolution,
+        }
+    }
+
+    fn voxel(&self, x: i64, y: i64, z: i64) -> f32 {
+        let [width, height, depth] = self.resolution.map(|count| count as i64);
+        if x < 0 || y < 0 || z < 0 || x >= width || y >= height || z >= depth {
+            return 0.0;
+        }
+        self.data[((z * height + y) * width + x) as usize]
+    }
+
+    //trilinear lookup at a position from 0 to 1 across the grid, nothing outside it
+    pub fn sample(&self, position: [f32; 3]) -> f32 {
+        let grid = [0, 1, 2].map(|i| position[i] * self.resolution[i] as f32 - 0.5);
+        let base = grid.map(|value| value.floor());
+        let fraction = [0, 1, 2].map(|i| grid[i] - base[i]);
+        let [x, y, z] = base.map(|value| value as i64);
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let weight: f32 = (0..3).map(|i| if offset[i] == 1 { fraction[i] } else { 1.0 - fraction[i] }).product();
+            value += weight * self.voxel(x + offset[0] as i64, y + offset[1] as i64, z + offset[2] as i64);
+        }
+        value
+    }
+
+    //the majorant cell holding a position from 0 to 1 across the grid
+    pub fn majorant(&self, position: [f32; 3]) -> f32 {
+        let cell = [0, 1, 2].map(|i| {
+            let index = (position[i] * self.resolution[i] as f32 / MAJORANT_CELL as f32).floor();
+            (index.max(0.0) as usize).min(self.majorant_resolution[i] - 1)
+        });
+        self.majorants[(cell[2] * self.majorant_resolution[1] + cell[1]) * self.majorant_resolution[0] + cell[0]]
+    }
+
+    //positions from 0 to 1 where the edges of the majorant cells lie along one axis
+    pub fn cell_edges(&self, axis: usize) -> impl Iterator<Item = f32> + '_ {
+        (1..self.majorant_resolution[axis]).map(move |cell| (cell * MAJORANT_CELL) as f32 / self.resolution[axis] as f32)
+    }
+}
+
+fn decode(bytes: &[u8]) -> Result<VoxelGrid, String> {
+    let mut position = 0;
+    let mut next_line = || -> Result<String, String> {
+        let end = bytes[position..].iter().position(|&byte| byte == b'\n').ok_or("header has no end")?;
+        let line = String::from_utf8_lossy(&bytes[position..position + end]).trim().to_string();
+        position += end + 1;
+        Ok(line)
+    };
+    if next_line()? != "VOXELS" {
+        return Err("not a voxel file".to_string());
+    }
+    let line = next_line()?;
+    let numbers = line.split_whitespace().map(|word| word.parse::<usize>().map_err(|_| format!("invalid resolution '{}'", word))).collect::<Result<Vec<usize>, String>>()?;
+    let resolution = match numbers.as_slice() {
+        &[x, y, z] if x > 0 && y > 0 && z > 0 => [x, y, z],
+        _ => return Err(format!("resolution needs 3 positive numbers, found '{}'", line)),
+    };
+    let count = resolution.iter().product::<usize>();
+    let body = &bytes[position..];
+    if body.len() < count * 4 {
+        return Err(format!("expected {} values but the file is too short", count));
+    }
+    let data = body.chunks_exact(4).take(count).map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]).max(0.0)).collect();
+    Ok(VoxelGrid::new(resolution, data))
+}